@@ -0,0 +1,116 @@
+// benchmarks of the hot loops of chain generation and lookup, on stable toolchains
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sm3::builder::{TableBuilder, TableParams};
+use sm3::my_sm3_impl::PrefixState;
+use sm3::rainbow::{
+    ChainHasher, Charset, PlaintextIter, PlaintextSpace, RainbowChain, RainbowIndex,
+};
+use sm3::table::{Budget, TableSet};
+
+const OPENSSL_SM3: sm3::Hash = sm3::OPENSSL_SM3;
+const MY_SM3: sm3::Hash = sm3::MY_SM3;
+
+// [a-z0-9]{1,8}, fitting 64-bit indices
+fn space() -> PlaintextSpace {
+    let charset = Charset::from_chars("abcdefghijklmnopqrstuvwxyz0123456789");
+    PlaintextSpace::from_charset(&charset, &(1..9)).unwrap()
+}
+
+fn bench_hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+    for &len in &[6usize, 16, 55] {
+        let input: Vec<u8> = (0..len).map(|_| rand::random::<u8>()).collect();
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::new("my", len), &input, |b, input| {
+            b.iter(|| MY_SM3(black_box(input)))
+        });
+        group.bench_with_input(BenchmarkId::new("openssl", len), &input, |b, input| {
+            b.iter(|| OPENSSL_SM3(black_box(input)))
+        });
+        // half of input as fixed prefix
+        let state = PrefixState::new(&input[..len / 2]);
+        let mut output = [0u8; 32];
+        group.bench_with_input(BenchmarkId::new("prefix", len), &input, |b, input| {
+            b.iter(|| state.hash(black_box(input), &mut output))
+        });
+    }
+    group.finish();
+}
+
+fn bench_index(c: &mut Criterion) {
+    let space = space();
+    let size = space.size() as u64;
+    let mut plaintext = vec![0u8; space.max_len()];
+    let hash = MY_SM3(b"abc");
+    let mut group = c.benchmark_group("index");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("to_plaintext", |b| {
+        let mut index = size / 2;
+        b.iter(|| {
+            index += 1;
+            RainbowIndex(black_box(index)).to_plaintext(&space, &mut plaintext)
+        })
+    });
+    group.bench_function("plaintext_iter", |b| {
+        let mut plaintexts = PlaintextIter::new(&space, space.size() / 2..space.size());
+        b.iter(|| plaintexts.next_plaintext().map(|p| p.len()))
+    });
+    group.bench_function("from_hash", |b| {
+        b.iter(|| RainbowIndex::<u64>::from_hash(black_box(&hash), 0, size, black_box(100)))
+    });
+    group.finish();
+}
+
+fn bench_chain(c: &mut Criterion) {
+    let space = space();
+    let hasher = ChainHasher::new(&space);
+    let mut group = c.benchmark_group("chain");
+    group.sample_size(20);
+    for &chain_len in &[100usize, 1000, 10000] {
+        group.throughput(Throughput::Elements(chain_len as u64));
+        group.bench_with_input(
+            BenchmarkId::new("from_index", chain_len),
+            &chain_len,
+            |b, &chain_len| {
+                let mut head = 0u64;
+                b.iter(|| {
+                    head += 1;
+                    RainbowChain::from_index(RainbowIndex(head), &space, &hasher, 0, chain_len, 0)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// candidate tails of all offsets cost about chain_len^2 / 2 hashes
+fn bench_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    group.sample_size(10);
+    for &chain_len in &[50u64, 100, 200, 400] {
+        let mut params =
+            TableParams::from_charset(Charset::from_chars("abcdefghijklmnopqrstuvwxyz"), 1, 6)
+                .unwrap();
+        params.num_chain = 100;
+        params.chain_len = chain_len;
+        let table = TableBuilder::new(params).build().unwrap();
+        let tables = TableSet::from_tables(vec![table], &[]).unwrap();
+        let progress = indicatif::ProgressBar::hidden();
+        group.throughput(Throughput::Elements(chain_len * (chain_len - 1) / 2));
+        group.bench_with_input(
+            BenchmarkId::new("search_hash", chain_len),
+            &tables,
+            |b, tables| {
+                b.iter(|| {
+                    let hash: [u8; 32] = rand::random();
+                    tables.search_hash(&tables.tables[0], &hash, &Budget::default(), &progress)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_hash, bench_index, bench_chain, bench_lookup);
+criterion_main!(benches);
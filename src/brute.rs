@@ -0,0 +1,131 @@
+// cracking hashes by enumerating a plain text space, faster than tables for small spaces
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use indicatif::ProgressBar;
+use log::*;
+use rayon::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::rainbow::*;
+use crate::table::{Budget, Coverage, Crack, HashResult};
+
+// reported as the table where plain texts are found
+pub const BRUTE_FORCE_NAME: &str = "<brute force>";
+
+// plain texts enumerated by each task between budget checks
+const BLOCK_SIZE: u64 = 1 << 16;
+
+// enumerate indices in range of space and check their hashes against all target hashes,
+// stopping when all are cracked unless all_preimages is set;
+// progress is increased once per plain text
+pub fn brute_force(
+    space: &PlaintextSpace,
+    range: Range<u128>,
+    target_hashes: &[[u8; 32]],
+    all_preimages: bool,
+    budget: &Budget,
+    progress: &ProgressBar,
+) -> Result<Vec<HashResult>> {
+    if range.start > range.end || range.end > space.size() {
+        return Err(Error::InvalidInput(format!(
+            "Range {}-{} out of plain text space of size {}",
+            range.start,
+            range.end,
+            space.size()
+        )));
+    }
+    if range.end - range.start > u64::MAX as u128 {
+        return Err(Error::InvalidInput(format!(
+            "Range {}-{} too large to enumerate",
+            range.start, range.end
+        )));
+    }
+
+    let targets: HashSet<[u8; 32]> = target_hashes.iter().cloned().collect();
+    let found: Mutex<HashMap<[u8; 32], Vec<Crack>>> = Mutex::new(HashMap::new());
+    // distinct hashes not cracked yet
+    let remaining = AtomicUsize::new(targets.len());
+    let searched = AtomicU64::new(0);
+    let total = (range.end - range.start) as u64;
+    info!(
+        "Enumerating {} plain texts from index {} for {} hashes",
+        total,
+        range.start,
+        targets.len()
+    );
+
+    // rounds of fixed prefixes of segments are shared by all plain texts
+    let prefix_states = space.prefix_states();
+    let num_block = total.div_ceil(BLOCK_SIZE);
+    (0..num_block).into_par_iter().for_each(|block| {
+        if budget.is_exhausted() || (!all_preimages && remaining.load(Ordering::Relaxed) == 0) {
+            return;
+        }
+        let start = range.start + (block * BLOCK_SIZE) as u128;
+        let end = (start + BLOCK_SIZE as u128).min(range.end);
+        let mut plaintexts = PlaintextIter::new(space, start..end);
+        let mut hash = [0u8; 32];
+        while plaintexts.next_plaintext().is_some() {
+            let plaintext = plaintexts.plaintext();
+            prefix_states[plaintexts.segment()].hash(plaintext, &mut hash);
+            if targets.contains(&hash) {
+                let plain = plaintext.to_vec();
+                info!(
+                    "Found plain text {:?} at index {}",
+                    plain,
+                    plaintexts.index()
+                );
+                let mut found = found.lock().unwrap();
+                let cracks = found.entry(hash).or_default();
+                if cracks.is_empty() {
+                    remaining.fetch_sub(1, Ordering::Relaxed);
+                }
+                cracks.push(Crack {
+                    plain,
+                    table: Some(BRUTE_FORCE_NAME.to_owned()),
+                    chain: None,
+                    pos: None,
+                });
+            }
+        }
+        searched.fetch_add((end - start) as u64, Ordering::Relaxed);
+        progress.inc((end - start) as u64);
+    });
+
+    let found = found.into_inner().unwrap();
+    let coverage = Coverage {
+        table: BRUTE_FORCE_NAME.to_owned(),
+        offsets: searched.into_inner() as usize,
+        chain_len: total as usize,
+    };
+    Ok(target_hashes
+        .iter()
+        .map(|hash| HashResult {
+            cracks: found.get(hash).cloned().unwrap_or_default(),
+            coverage: vec![coverage.clone()],
+            ..Default::default()
+        })
+        .collect())
+}
+
+// segments of space not in any of the covered spaces, None if all are covered
+pub fn uncovered_space(
+    space: &PlaintextSpace,
+    covered: &[&PlaintextSpace],
+) -> Option<PlaintextSpace> {
+    let segments: Vec<Segment> = space
+        .segments
+        .iter()
+        .filter(|s| !covered.iter().any(|c| c.segments.contains(s)))
+        .cloned()
+        .collect();
+    if segments.is_empty() {
+        None
+    } else {
+        PlaintextSpace::from_segments(segments).ok()
+    }
+}
@@ -0,0 +1,325 @@
+// generating rainbow tables
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use log::*;
+use rayon::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+use crate::rainbow::*;
+use crate::table::{write_table, RainbowTable};
+use crate::Hash;
+
+// parameters of a table to generate
+#[derive(Clone, Debug)]
+pub struct TableParams {
+    pub space: PlaintextSpace,
+    // set if space consists of all strings of charset with min_length..=max_length elements,
+    // which is then stored in header instead of the plain text space descriptor
+    pub charset: Option<Charset>,
+    pub min_length: u32,
+    pub max_length: u32,
+    pub num_chain: u64,
+    pub chain_len: u64,
+    pub table_index: u64,
+    pub seed: u64,
+    pub random_head: bool,
+}
+
+impl TableParams {
+    pub fn from_space(space: PlaintextSpace) -> Self {
+        TableParams {
+            min_length: space.min_len() as u32,
+            max_length: space.max_len() as u32,
+            space,
+            charset: None,
+            num_chain: 10000,
+            chain_len: 10000,
+            table_index: 0,
+            seed: 0,
+            random_head: false,
+        }
+    }
+
+    pub fn from_charset(charset: Charset, min_length: u32, max_length: u32) -> Result<Self> {
+        if charset.is_empty() || charset.min_elem_len() == 0 {
+            return Err(Error::InvalidInput(
+                "Charset must not be empty or contain empty tokens".to_owned(),
+            ));
+        }
+        check_lengths(min_length, max_length)?;
+        let len_range = (min_length as usize)..(max_length + 1) as usize;
+        let space =
+            PlaintextSpace::from_charset(&charset, &len_range).map_err(Error::InvalidInput)?;
+        Ok(TableParams {
+            charset: Some(charset),
+            min_length,
+            max_length,
+            ..TableParams::from_space(space)
+        })
+    }
+
+    pub fn index_width(&self) -> u64 {
+        index_width_for(self.space.size())
+    }
+
+    // encoded charset and plain text space descriptor, only one of them is used
+    fn descriptors(&self) -> (Vec<u8>, Vec<u8>) {
+        match &self.charset {
+            Some(c) => (c.encode(), Vec::new()),
+            None => (Vec::new(), self.space.encode()),
+        }
+    }
+
+    // chains must start from distinct plain texts, and sequential heads of this table
+    // (table_index * num_chain onwards) must be inside the space
+    pub fn validate(&self) -> Result<()> {
+        check_lengths(self.min_length, self.max_length)?;
+        let size = self.space.size();
+        if self.num_chain as u128 > size {
+            return Err(Error::InvalidInput(format!(
+                "Number of chains {} exceeds plain text space of size {}",
+                self.num_chain, size
+            )));
+        }
+        let end = (self.table_index as u128 + 1) * self.num_chain as u128;
+        if !self.random_head && end > size {
+            return Err(Error::InvalidInput(format!(
+                "Heads {}-{} of table {} out of plain text space of size {}, use another table index or random heads",
+                end - self.num_chain as u128,
+                end,
+                self.table_index,
+                size
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn header(&self) -> RainbowTableHeader {
+        let (charset, space_desc) = self.descriptors();
+        RainbowTableHeader {
+            magic: RAINBOW_TABLE_HEADER_MAGIC,
+            version: RAINBOW_TABLE_VERSION,
+            num_chain: self.num_chain,
+            chain_len: self.chain_len,
+            table_index: self.table_index,
+            min_length: self.min_length,
+            max_length: self.max_length,
+            charset_length: charset.len() as u64,
+            seed: self.seed,
+            space_length: space_desc.len() as u64,
+            index_width: self.index_width(),
+        }
+    }
+}
+
+// readers reject empty plain texts and empty length ranges
+fn check_lengths(min_length: u32, max_length: u32) -> Result<()> {
+    if min_length == 0 || min_length > max_length {
+        return Err(Error::InvalidInput(format!(
+            "Invalid plain text lengths {}-{}: minimal length must be positive and not above maximal length",
+            min_length, max_length
+        )));
+    }
+    Ok(())
+}
+
+// rounds of random chains in a row that add no new tail before generation fails
+const MAX_STALLED_ROUNDS: u32 = 16;
+
+// callback with (chains generated, chains required), return false to cancel
+type ProgressCallback<'a> = Box<dyn Fn(u64, u64) -> bool + Sync + 'a>;
+
+pub struct TableBuilder<'a> {
+    params: TableParams,
+    hasher: ChainHasher,
+    progress: Option<ProgressCallback<'a>>,
+}
+
+impl<'a> TableBuilder<'a> {
+    pub fn new(params: TableParams) -> Self {
+        TableBuilder {
+            hasher: ChainHasher::new(&params.space),
+            params,
+            progress: None,
+        }
+    }
+
+    pub fn hasher(mut self, hasher: Hash) -> Self {
+        self.hasher = ChainHasher::Plain(hasher);
+        self
+    }
+
+    // called once for every generated chain, possibly from several threads
+    pub fn progress<F: Fn(u64, u64) -> bool + Sync + 'a>(mut self, callback: F) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn params(&self) -> &TableParams {
+        &self.params
+    }
+
+    // generate the table in memory
+    pub fn build(&self) -> Result<RainbowTable> {
+        self.params.validate()?;
+        let header = self.params.header();
+        let (charset, space_desc) = self.params.descriptors();
+        let name = format!("<table {}>", self.params.table_index);
+        if header.index_width == u64::WIDTH {
+            let chains = self.generate_chains::<u64>()?;
+            Ok(RainbowTable::from_chains(
+                &name, header, charset, space_desc, &chains,
+            ))
+        } else {
+            let chains = self.generate_chains::<u128>()?;
+            Ok(RainbowTable::from_chains(
+                &name, header, charset, space_desc, &chains,
+            ))
+        }
+    }
+
+    // generate the table and write it to output, return total length
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<usize> {
+        self.write_with_metadata(output, Metadata::default)
+    }
+
+    // same as write_to, with metadata made once chains are generated written after them;
+    // chains go to output as they are, without another copy of the table in memory
+    pub fn write_with_metadata<W: Write, F: FnOnce() -> Metadata>(
+        &self,
+        output: &mut W,
+        metadata: F,
+    ) -> Result<usize> {
+        self.params.validate()?;
+        let header = self.params.header();
+        let (charset, space_desc) = self.params.descriptors();
+        let len = if header.index_width == u64::WIDTH {
+            let chains = self.generate_chains::<u64>()?;
+            write_table(output, &header, &charset, &space_desc, &chains, &metadata())?
+        } else {
+            let chains = self.generate_chains::<u128>()?;
+            write_table(output, &header, &charset, &space_desc, &chains, &metadata())?
+        };
+        Ok(len)
+    }
+
+    // generate sorted chains with distinct tails
+    fn generate_chains<I: IndexInt>(&self) -> Result<Vec<RainbowChain<I>>> {
+        let params = &self.params;
+        let num_chain = params.num_chain;
+        let chain_len = params.chain_len as usize;
+        let table_index = params.table_index;
+        let seed = params.seed;
+        let space = &params.space;
+        let plaintext_space_size = I::from_u128(space.size());
+
+        let generated = AtomicU64::new(0);
+        let cancelled = AtomicBool::new(false);
+        // build one chain unless cancelled
+        let make_chain = |head: RainbowIndex<I>| {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            let chain = RainbowChain::from_index(head, space, &self.hasher, 0, chain_len, 0);
+            trace!("Generate chain: {:?}\n", chain);
+            let done = generated.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(progress) = &self.progress {
+                if !progress(done, num_chain) {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            }
+            Some(chain)
+        };
+        let check_cancelled = || {
+            if cancelled.load(Ordering::Relaxed) {
+                Err(Error::Cancelled)
+            } else {
+                Ok(())
+            }
+        };
+
+        // generate chain in parallel
+        let start_index = table_index as u128 * num_chain as u128;
+        if params.random_head {
+            info!("Start generating rainbow chains using random numbers");
+        } else {
+            info!(
+                "Start generating rainbow chains from index {} to {}",
+                start_index,
+                start_index + num_chain as u128
+            );
+        }
+
+        let mut chains: Vec<_> = (0..num_chain)
+            .into_par_iter()
+            .filter_map(|i| {
+                let head = if params.random_head {
+                    RainbowIndex::from_seed(seed, table_index, 0, i, plaintext_space_size)
+                } else {
+                    RainbowIndex(I::from_u128(start_index + i as u128))
+                };
+                make_chain(head)
+            })
+            .collect();
+        check_cancelled()?;
+        info!("Finish generating rainbow chains");
+
+        info!("Start sorting rainbow chains");
+        chains.sort();
+        chains.dedup();
+        info!("Finish sorting rainbow chains");
+        info!(
+            "Table size after removing duplicated tails: {}",
+            chains.len()
+        );
+
+        // generate from random indices until reaching num_chain; a small space may not
+        // have enough distinct tails, so give up after some rounds without new ones
+        let mut round = 0;
+        let mut stalled_rounds = 0;
+        while chains.len() < num_chain as usize {
+            if stalled_rounds == MAX_STALLED_ROUNDS {
+                return Err(Error::InvalidInput(format!(
+                    "Only {} distinct tails found after {} rounds, use fewer chains",
+                    chains.len(),
+                    round
+                )));
+            }
+            let num_remain_chain = (num_chain as usize) - chains.len();
+            round += 1;
+            info!(
+                "Generating remaining {} chains from random numbers (round {})",
+                num_remain_chain, round
+            );
+            // generate random chains
+            let mut random_chains: Vec<_> = (0..num_remain_chain as u64)
+                .into_par_iter()
+                .filter_map(|i| {
+                    make_chain(RainbowIndex::from_seed(
+                        seed,
+                        table_index,
+                        round,
+                        i,
+                        plaintext_space_size,
+                    ))
+                })
+                .collect();
+            check_cancelled()?;
+            let num_before = chains.len();
+            chains.append(&mut random_chains);
+            chains.sort();
+            chains.dedup();
+            info!("New chain number: {}", chains.len());
+            if chains.len() == num_before {
+                stalled_rounds += 1;
+            } else {
+                stalled_rounds = 0;
+            }
+        }
+
+        Ok(chains)
+    }
+}
@@ -0,0 +1,193 @@
+// candidate tails of hashes saved across lookups, valid for all tables
+// with the same plain text space, chain length and reduction functions
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::*;
+
+use crate::error::{Error, Result};
+
+// "HCTAILCA" (Harry Chen TAIL CAche) in little endian
+pub const TAIL_CACHE_MAGIC: u64 = 0x41434c4941544348;
+pub const TAIL_CACHE_VERSION: u64 = 1;
+
+// file layout: magic, version, then records of
+// key (32 bytes), hash (32 bytes), index width, number of tails and the tails,
+// where tails[i] is the candidate tail at offset chain_len - 1 - i;
+// a later record of the same key and hash supersedes earlier ones
+pub struct TailCache {
+    path: PathBuf,
+    key: [u8; 32],
+    index_width: u64,
+    entries: Mutex<HashMap<[u8; 32], Vec<u128>>>,
+    // hashes with more tails than saved in file
+    dirty: Mutex<HashSet<[u8; 32]>>,
+    // candidate tails taken from cache instead of computed
+    hits: AtomicU64,
+}
+
+// key, hash, index width and candidate tails of one record
+type Record = ([u8; 32], [u8; 32], u64, Vec<u128>);
+
+fn read_record<R: Read>(reader: &mut R) -> std::io::Result<Record> {
+    let mut key = [0u8; 32];
+    reader.read_exact(&mut key)?;
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash)?;
+    let index_width = reader.read_u64::<LittleEndian>()?;
+    // count is not trusted for allocation, the file ends first if it is bogus
+    let count = reader.read_u64::<LittleEndian>()?;
+    let mut tails = Vec::new();
+    for _ in 0..count {
+        tails.push(match index_width {
+            64 => reader.read_u64::<LittleEndian>()? as u128,
+            _ => reader.read_u128::<LittleEndian>()?,
+        });
+    }
+    Ok((key, hash, index_width, tails))
+}
+
+impl TailCache {
+    // load entries of the given key, a missing file gives an empty cache
+    pub fn open<P: AsRef<Path>>(path: P, key: [u8; 32], index_width: u64) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let name = path.display().to_string();
+        let mut entries = HashMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                let mut reader = BufReader::new(file);
+                let invalid = |e: String| Error::InvalidCache(name.clone(), e);
+                let truncated = |e: std::io::Error| match e.kind() {
+                    ErrorKind::UnexpectedEof => invalid("truncated".to_owned()),
+                    _ => Error::Io(e),
+                };
+                let magic = reader.read_u64::<LittleEndian>().map_err(truncated)?;
+                if magic != TAIL_CACHE_MAGIC {
+                    return Err(invalid(format!("bad magic {:#018x}", magic)));
+                }
+                let version = reader.read_u64::<LittleEndian>().map_err(truncated)?;
+                if version != TAIL_CACHE_VERSION {
+                    return Err(invalid(format!(
+                        "unsupported version {} (expected {})",
+                        version, TAIL_CACHE_VERSION
+                    )));
+                }
+                let mut other = 0;
+                while !reader.fill_buf()?.is_empty() {
+                    let (record_key, hash, width, tails) =
+                        read_record(&mut reader).map_err(truncated)?;
+                    if record_key == key && width == index_width {
+                        entries.insert(hash, tails);
+                    } else {
+                        other += 1;
+                    }
+                }
+                info!(
+                    "Loaded candidate tails of {} hashes from {} ({} records of other tables)",
+                    entries.len(),
+                    name,
+                    other
+                );
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::Io(std::io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", name, e),
+                )))
+            }
+        }
+        Ok(TailCache {
+            path,
+            key,
+            index_width,
+            entries: Mutex::new(entries),
+            dirty: Mutex::new(HashSet::new()),
+            hits: AtomicU64::new(0),
+        })
+    }
+
+    // number of candidate tails taken from cache since opened
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // cached tails of a hash, empty if none
+    pub fn get(&self, hash: &[u8; 32]) -> Vec<u128> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(hash)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // keep tails if more than cached
+    pub fn insert(&self, hash: [u8; 32], tails: Vec<u128>) {
+        let mut entries = self.entries.lock().unwrap();
+        let cached = entries.get(&hash).map_or(0, |t| t.len());
+        if tails.len() > cached {
+            entries.insert(hash, tails);
+            self.dirty.lock().unwrap().insert(hash);
+        }
+    }
+
+    // append new and extended entries to file, return number of records written
+    pub fn save(&self) -> Result<usize> {
+        let mut dirty = self.dirty.lock().unwrap();
+        if dirty.is_empty() {
+            return Ok(0);
+        }
+        let entries = self.entries.lock().unwrap();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_new {
+            writer.write_u64::<LittleEndian>(TAIL_CACHE_MAGIC)?;
+            writer.write_u64::<LittleEndian>(TAIL_CACHE_VERSION)?;
+        }
+        for hash in dirty.iter() {
+            let tails = &entries[hash];
+            writer.write_all(&self.key)?;
+            writer.write_all(hash)?;
+            writer.write_u64::<LittleEndian>(self.index_width)?;
+            writer.write_u64::<LittleEndian>(tails.len() as u64)?;
+            for &tail in tails {
+                match self.index_width {
+                    64 => writer.write_u64::<LittleEndian>(tail as u64)?,
+                    _ => writer.write_u128::<LittleEndian>(tail)?,
+                }
+            }
+        }
+        writer.flush()?;
+        let written = dirty.len();
+        dirty.clear();
+        info!(
+            "Saved candidate tails of {} hashes to {}",
+            written,
+            self.path.display()
+        );
+        Ok(written)
+    }
+}
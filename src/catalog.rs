@@ -0,0 +1,81 @@
+// rainbow tables found in a directory, grouped into table sets of compatible parameters
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::*;
+
+use crate::error::{Error, Result};
+use crate::table::{RainbowTable, TableSet};
+use crate::wordlist::Wordlist;
+
+pub struct Catalog {
+    // smallest plain text space first
+    pub groups: Vec<TableSet>,
+    // files or groups that cannot be used, with the reason
+    pub skipped: Vec<(PathBuf, Error)>,
+}
+
+impl Catalog {
+    // open all .dat files in a directory (not recursively) and group them by
+    // charset, lengths, plain text space, chain parameters and table version;
+    // a group whose plain text space cannot be built (e.g. its wordlist is not given)
+    // is skipped as a whole
+    pub fn scan<P: AsRef<Path>>(dir: P, wordlists: &[Arc<Wordlist>]) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(|e| {
+            Error::Io(std::io::Error::new(
+                e.kind(),
+                format!("{}: {}", dir.display(), e),
+            ))
+        })? {
+            let path = entry?.path();
+            if path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("dat"))
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut skipped = Vec::new();
+        let mut grouped: Vec<Vec<RainbowTable>> = Vec::new();
+        for path in paths {
+            let table = match RainbowTable::open(&path) {
+                Ok(table) => table,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    skipped.push((path, e));
+                    continue;
+                }
+            };
+            match grouped.iter_mut().find(|g| g[0].is_compatible(&table)) {
+                Some(group) => group.push(table),
+                None => grouped.push(vec![table]),
+            }
+        }
+
+        let mut groups = Vec::new();
+        for tables in grouped {
+            let path = tables[0].path.clone();
+            match TableSet::from_tables(tables, wordlists) {
+                Ok(set) => groups.push(set),
+                Err(e) => {
+                    warn!("Skipping group of {}: {}", path.display(), e);
+                    skipped.push((path, e));
+                }
+            }
+        }
+        groups.sort_by_key(|set| set.space.size());
+        info!(
+            "Found {} groups of tables in {}, {} skipped",
+            groups.len(),
+            dir.display(),
+            skipped.len()
+        );
+        Ok(Catalog { groups, skipped })
+    }
+}
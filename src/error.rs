@@ -0,0 +1,87 @@
+// errors of table handling, each mapped to an exit code of the binaries
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    // magic number found in file
+    BadMagic(u64),
+    // version found in file
+    UnsupportedVersion(u64),
+    // file name of a table without version
+    LegacyTable(String),
+    // file name
+    Truncated(String),
+    InvalidHeader(String),
+    // name of the table that differs from the first one
+    InconsistentTables(String),
+    InvalidHash(String),
+    InvalidInput(String),
+    // file name and what is wrong with it
+    InvalidCache(String, String),
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidInput(_) => 1,
+            Error::Io(_) => 2,
+            Error::BadMagic(_) => 3,
+            Error::UnsupportedVersion(_) | Error::LegacyTable(_) => 4,
+            Error::Truncated(_) => 5,
+            Error::InvalidHeader(_) => 6,
+            Error::InconsistentTables(_) => 7,
+            Error::InvalidHash(_) => 8,
+            Error::InvalidCache(_, _) => 9,
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::BadMagic(magic) => write!(f, "Not a rainbow table: bad magic {:#018x}", magic),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported table version {} (expected {})",
+                version,
+                crate::rainbow::RAINBOW_TABLE_VERSION
+            ),
+            Error::LegacyTable(name) => write!(
+                f,
+                "Table {} was written before table versioning and has to be regenerated",
+                name
+            ),
+            Error::Truncated(name) => write!(f, "Table {} is truncated", name),
+            Error::InvalidHeader(e) => write!(f, "Invalid table header: {}", e),
+            Error::InconsistentTables(name) => {
+                write!(f, "Table {} has inconsistent parameters", name)
+            }
+            Error::InvalidHash(e) => write!(f, "Invalid hash: {}", e),
+            Error::InvalidInput(e) => write!(f, "{}", e),
+            Error::InvalidCache(name, e) => write!(f, "Invalid tail cache {}: {}", name, e),
+            Error::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -1,15 +1,18 @@
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::File;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use clap::Clap;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::*;
-use rand::Rng;
-use rayon::prelude::*;
-use sm3::rainbow::{RainbowChain, RainbowIndex, RainbowTableHeader, RAINBOW_TABLE_HEADER_MAGIC};
-
-mod util;
+use sm3::builder::{TableBuilder, TableParams};
+use sm3::error::Error;
+use sm3::mask;
+use sm3::metadata::Metadata;
+use sm3::rainbow::{Charset, PlaintextSpace, Segment};
+use sm3::wordlist::{self, Wordlist};
 
 #[derive(Clap, Debug)]
 #[clap(
@@ -24,13 +27,38 @@ pub struct GeneratorOptions {
         long,
         default_value = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789"
     )]
+    /// Charset of plain text, each UTF-8 code point is an element
     pub charset: String,
+    #[clap(short = 'T', long)]
+    /// Comma-separated tokens used as charset elements instead of code points (e.g. "zhang,wang,li")
+    pub tokens: Option<String>,
     #[clap(short = 'm', long, default_value = "5")]
-    /// Minimal length of plain text
+    /// Minimal length (number of charset elements) of plain text
     pub min_length: u32,
     #[clap(short = 'M', long, default_value = "6")]
-    /// Maximal length of plain text
+    /// Maximal length (number of charset elements) of plain text
     pub max_length: u32,
+    #[clap(short = 'k', long)]
+    /// Hashcat-style masks (e.g. "?u?l?l?l?d?d") whose union is the plain text space, overrides charset and lengths
+    pub mask: Vec<String>,
+    #[clap(short = '1', long)]
+    /// Custom charset referenced by ?1 in mask
+    pub custom_charset1: Option<String>,
+    #[clap(short = '2', long)]
+    /// Custom charset referenced by ?2 in mask
+    pub custom_charset2: Option<String>,
+    #[clap(short = '3', long)]
+    /// Custom charset referenced by ?3 in mask
+    pub custom_charset3: Option<String>,
+    #[clap(short = '4', long)]
+    /// Custom charset referenced by ?4 in mask
+    pub custom_charset4: Option<String>,
+    #[clap(short = 'w', long)]
+    /// Wordlist (one word per line) added to plain text space, overrides charset and lengths
+    pub wordlist: Option<String>,
+    #[clap(short = 'R', long, default_value = ":")]
+    /// Comma-separated rules applied to each word, made of ':' (nothing), 'l' (lower), 'u' (upper), 'c' (capitalize), 't' (toggle case), 'e' (leetspeak), 'dN' (append N digits)
+    pub rules: String,
     #[clap(short = 'n', long, default_value = "10000")]
     /// Number of chains in each table
     pub num_chain: u64,
@@ -43,35 +71,138 @@ pub struct GeneratorOptions {
     #[clap(short = 'r', long)]
     /// Use random plain text as head instead of sequential traversal
     pub random_head: bool,
+    #[clap(short = 's', long)]
+    /// Seed of random heads (chosen randomly if not specified, always stored in table)
+    pub seed: Option<u64>,
     #[clap(short = 'o', long)]
     /// Output table name (synthesized if not specified)
     pub output_file: Option<String>,
     #[clap(short = 'f', long)]
     /// Forcibly overwrite existing output file
     pub force_overwrite: bool,
+    #[clap(long)]
+    /// User tags stored in table metadata, in format of key=value
+    pub tag: Vec<String>,
+    #[clap(long)]
+    /// Leave creation time, host name and generation time out of metadata, so that the same options and seed give identical files
+    pub reproducible: bool,
+}
+
+// name of this machine, if known
+fn host_name() -> Option<String> {
+    std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|h| h.trim().to_owned())
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
 }
 
 fn run_generate(opts: &GeneratorOptions) {
     // read options
-    let charset: &[u8] = opts.charset.as_bytes();
-    let plaintext_len_range = (opts.min_length as usize)..(opts.max_length + 1) as usize;
-    let num_chain = opts.num_chain;
-    let chain_len = opts.chain_len;
-    let table_index = opts.table_index;
-    let plaintext_lens = util::generate_cumulative_lengths(&plaintext_len_range, charset.len());
-    let plaintext_space_size = plaintext_lens[plaintext_len_range.end - 1];
+    let seed = opts.seed.unwrap_or_else(rand::random);
+    info!("Using random seed {}", seed);
+    let tags: Vec<(String, String)> = opts
+        .tag
+        .iter()
+        .map(|t| {
+            let mut parts = t.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => (key.to_owned(), value.to_owned()),
+                _ => {
+                    error!("Invalid tag {}, expecting key=value", t);
+                    std::process::exit(1);
+                }
+            }
+        })
+        .collect();
+    let params = if !opts.mask.is_empty() || opts.wordlist.is_some() {
+        let custom_charsets = [
+            opts.custom_charset1.clone(),
+            opts.custom_charset2.clone(),
+            opts.custom_charset3.clone(),
+            opts.custom_charset4.clone(),
+        ];
+        let mut segments = Vec::new();
+        for m in &opts.mask {
+            let positions = mask::parse_mask(m, &custom_charsets).unwrap_or_else(|e| {
+                error!("Invalid mask {}: {}", m, e);
+                std::process::exit(1);
+            });
+            segments.push(Segment::Mask { positions });
+        }
+        if let Some(file) = &opts.wordlist {
+            let wordlist = Wordlist::load(file).unwrap_or_else(|e| {
+                error!("Cannot read wordlist {}: {}", file, e);
+                std::process::exit(Error::Io(e).exit_code());
+            });
+            if wordlist.words.is_empty() {
+                error!("Wordlist {} is empty", file);
+                std::process::exit(1);
+            }
+            let rules = wordlist::parse_rules(&opts.rules).unwrap_or_else(|e| {
+                error!("Invalid rules {}: {}", opts.rules, e);
+                std::process::exit(1);
+            });
+            info!(
+                "Loaded {} words from {} with digest {}",
+                wordlist.words.len(),
+                file,
+                hex::encode(wordlist.digest)
+            );
+            segments.push(Segment::Wordlist {
+                wordlist: Arc::new(wordlist),
+                rules,
+            });
+        }
+        PlaintextSpace::from_segments(segments)
+            .map(TableParams::from_space)
+            .map_err(Error::InvalidInput)
+    } else {
+        let charset = match &opts.tokens {
+            Some(tokens) => Charset::from_tokens(&tokens.split(',').collect::<Vec<_>>()),
+            None => Charset::from_chars(&opts.charset),
+        };
+        info!("Using charset with {} elements: {}", charset.len(), charset);
+        TableParams::from_charset(charset, opts.min_length, opts.max_length)
+    };
+    let params = TableParams {
+        num_chain: opts.num_chain,
+        chain_len: opts.chain_len,
+        table_index: opts.table_index,
+        seed,
+        random_head: opts.random_head,
+        ..params.unwrap_or_else(|e| {
+            error!("Invalid plain text space: {}", e);
+            std::process::exit(e.exit_code());
+        })
+    };
+    if let Err(e) = params.validate() {
+        error!("Invalid table parameters: {}", e);
+        std::process::exit(e.exit_code());
+    }
     info!(
-        "Plain text count: {:?}, space size: {}",
-        plaintext_lens, plaintext_space_size
+        "Plain text count: {:?}, space size: {}, index width: {}",
+        params.space.offsets,
+        params.space.size(),
+        params.index_width()
     );
 
-    // try to open file for writing
+    // check output file before generating
     let output_file = match &opts.output_file {
         Some(file) => file.to_owned(),
         None => {
             format!(
-                "sm3_m{}_M{}_l{}_n{}_i{:04}.dat",
-                opts.min_length, opts.max_length, chain_len, num_chain, table_index
+                "sm3_{}m{}_M{}_l{}_n{}_i{:04}.dat",
+                if params.charset.is_none() {
+                    "mask_"
+                } else {
+                    ""
+                },
+                params.min_length,
+                params.max_length,
+                params.chain_len,
+                params.num_chain,
+                params.table_index
             )
         }
     };
@@ -83,15 +214,9 @@ fn run_generate(opts: &GeneratorOptions) {
         }
         warn!("Overwriting {} due to force flag", &output_file);
     }
-    let mut output = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&output_file)
-        .expect("Cannot open output file");
 
     // show progress bar
-    let progress = ProgressBar::new(num_chain);
+    let progress = ProgressBar::new(params.num_chain);
     progress.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -100,143 +225,53 @@ fn run_generate(opts: &GeneratorOptions) {
             .progress_chars("#>-"),
     );
 
-    // generate chain in parallel
-    let mut rng = rand::thread_rng();
-
-    let initial_indices: Vec<_> = if opts.random_head {
-        info!("Start generating rainbow chains using random numbers");
-        (0..num_chain)
-            .map(|_| rng.gen_range(0..plaintext_space_size))
-            .collect()
-    } else {
-        let start_index = table_index * num_chain;
-        let end_index = start_index + num_chain;
-        info!(
-            "Start generating rainbow chains from index {} to {}",
-            start_index, end_index
-        );
-        (start_index..end_index).collect()
-    };
-
-    let mut chains: Vec<_> = initial_indices
-        .into_par_iter()
-        .map(|i| {
-            let head = RainbowIndex(i);
-            let chain = RainbowChain::from_index(
-                head,
-                charset,
-                &plaintext_len_range,
-                plaintext_lens.as_ref(),
-                0,
-                chain_len as usize,
-                0,
-            );
-            trace!("Generate chain: {:?}\n", chain);
-            progress.inc(1);
-            chain
+    let mut output = File::create(&output_file).unwrap_or_else(|err| {
+        error!("Error creating file: {:?}", err);
+        std::process::exit(Error::Io(err).exit_code());
+    });
+    let start = Instant::now();
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let generated = AtomicU64::new(0);
+    let result = TableBuilder::new(params)
+        .progress(|done, _| {
+            progress.set_position(done);
+            generated.fetch_max(done, Ordering::Relaxed);
+            true
         })
-        .collect();
-
-    progress.finish_and_clear();
-    info!("Finish generating rainbow chains");
-
-    // process progress bar
-    info!("Start sorting rainbow chains");
-    chains.sort();
-    chains.dedup();
-    info!("Finish sorting rainbow chains");
-    info!(
-        "Table size after removing duplicated tails: {}",
-        chains.len()
-    );
-
-    // generate from random indices until reaching num_chain
-    while chains.len() < num_chain as usize {
-        let num_remain_chain = (num_chain as usize) - chains.len();
-        info!(
-            "Generating remaining {} chains from random numbers",
-            num_remain_chain
-        );
-        // generate random indices
-        let remaining_indices: Vec<_> = (0..num_remain_chain)
-            .map(|_| rng.gen_range(0..plaintext_space_size))
-            .collect();
-        // generate random chains
-        let mut random_chains: Vec<_> = (0..num_remain_chain)
-            .into_par_iter()
-            .map(|i| {
-                let head = RainbowIndex(remaining_indices[i]);
-                let chain = RainbowChain::from_index(
-                    head,
-                    charset,
-                    &plaintext_len_range,
-                    plaintext_lens.as_ref(),
-                    0,
-                    chain_len as usize,
-                    0,
-                );
-                trace!("Generate chain: {:?}\n", chain);
-                progress.inc(1);
-                chain
-            })
-            .collect();
-        chains.append(&mut random_chains);
-        chains.sort();
-        chains.dedup();
-        info!("New chain number: {}", chains.len());
-    }
-
-    // write rainbow table header to file
-    let header = RainbowTableHeader {
-        magic: RAINBOW_TABLE_HEADER_MAGIC,
-        num_chain,
-        chain_len,
-        table_index,
-        min_length: opts.min_length,
-        max_length: opts.max_length,
-        charset_length: charset.len() as u64,
-    };
-    let header_ptr = unsafe {
-        std::slice::from_raw_parts(
-            (&header as *const RainbowTableHeader) as *const u8,
-            std::mem::size_of::<RainbowTableHeader>(),
-        )
-    };
-    output
-        .write_all(&header_ptr)
-        .expect("Failed to write rainbow file header");
-    output
-        .write_all(&charset)
-        .expect("Failed to write charset to header");
-
-    // pad to 8 bytes
-    let padding_len = if charset.len() % 8 != 0 {
-        8 - charset.len() % 8
-    } else {
-        0
-    };
-    let padding = [0u8; 8];
-    output
-        .write_all(&padding[..padding_len])
-        .expect("Failed to write padding to header");
-
-    // write sorted rainbow chains to file
-    match output.write(unsafe {
-        std::slice::from_raw_parts(
-            chains.as_ptr() as *const u8,
-            chains.len() * std::mem::size_of::<RainbowChain>(),
-        )
-    }) {
-        Ok(len) => {
-            let total_len = header_ptr.len() + charset.len() + padding_len + len;
+        .write_with_metadata(&mut output, || {
+            progress.finish_and_clear();
+            let duration_ms = start.elapsed().as_millis() as u64;
+            info!("Generated chains in {} ms", duration_ms);
+            // seed is kept in header; time and host differ between runs
+            let (created, host, duration_ms) = if opts.reproducible {
+                (None, None, None)
+            } else {
+                (Some(created), host_name(), Some(duration_ms))
+            };
+            Metadata {
+                generator: Some(format!("generate_rt {}", env!("CARGO_PKG_VERSION"))),
+                created,
+                host,
+                duration_ms,
+                chains_generated: Some(generated.load(Ordering::Relaxed)),
+                tags,
+                ..Default::default()
+            }
+        });
+    match result {
+        Ok(total_len) => {
             info!(
                 "Successfully writing {} bytes to {}",
                 total_len, &output_file
             );
         }
         Err(err) => {
-            error!("Error writing file: {:?}", err);
-            std::process::exit(2);
+            error!("Cannot generate table: {}", err);
+            let _ = std::fs::remove_file(&output_file);
+            std::process::exit(err.exit_code());
         }
     }
 }
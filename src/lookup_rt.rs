@@ -1,18 +1,23 @@
 #![feature(osstring_ascii)]
 
-use std::fs::{File, OpenOptions};
-use std::io::Read;
-use std::path::Path;
+use std::fs::File;
+use std::io::BufReader;
 
 use clap::Clap;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::*;
-use memmap::{Mmap, MmapOptions};
-use rayon::prelude::*;
-use sm3::rainbow::{RainbowChain, RainbowIndex, RainbowTableHeader};
+use serde::Serialize;
+use sm3::brute::{brute_force, uncovered_space, BRUTE_FORCE_NAME};
+use sm3::catalog::Catalog;
+use sm3::error::Error;
+use sm3::potfile::{encode_plain, Potfile};
+use sm3::rainbow::{Charset, PlaintextSpace};
+use sm3::table::{Budget, Coverage, Crack, HashResult, TableSet};
+use sm3::target::{read_targets, Target};
+use sm3::wordlist::Wordlist;
 use std::collections::HashMap;
-
-mod util;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Clap, Debug)]
 #[clap(
@@ -22,229 +27,773 @@ mod util;
     about = "Lookup hashes in rainbow tables of SM3 hash algorithm"
 )]
 pub struct LookupOptions {
-    #[clap(short = 'h', long, required = true)]
+    #[clap(short = 'h', long)]
+    /// Hashes to crack, in format of hash, user:hash or hash:salt
     pub hash: Vec<String>,
-    #[clap(short = 't', long, required = true)]
+    #[clap(short = 'H', long)]
+    /// Files containing one hash per line ("-" for stdin)
+    pub hash_file: Vec<String>,
+    #[clap(short = 't', long)]
     pub table_files: Vec<String>,
+    #[clap(short = 'd', long)]
+    /// Directory of tables, grouped by parameters and searched group by group instead of -t
+    pub catalog: Option<String>,
+    #[clap(short = 'w', long)]
+    /// Wordlists used by tables (matched by content digest)
+    pub wordlist: Vec<String>,
+    #[clap(short = 'b', long)]
+    /// Look up all hashes together in one pass over each table
+    pub batch: bool,
+    #[clap(short = 'p', long)]
+    /// Potfile of cracked hashes, consulted before tables and appended with new results
+    pub potfile: Option<String>,
+    #[clap(long, conflicts_with = "left")]
+    /// Print cracked hashes in potfile as user:hash:plain text, without looking up tables
+    pub show: bool,
+    #[clap(long)]
+    /// Print hashes not cracked in potfile, without looking up tables
+    pub left: bool,
+    #[clap(short = 'f', long, default_value = "plain", possible_values = &["plain", "json", "csv"])]
+    /// Output format: plain text, one JSON object per line or CSV
+    pub format: String,
+    #[clap(long)]
+    /// Stop looking up after this many seconds in total
+    pub timeout: Option<f64>,
+    #[clap(long)]
+    /// Stop looking up a hash after this many seconds (ignored in batch mode)
+    pub hash_timeout: Option<f64>,
+    #[clap(long)]
+    /// Keep searching all offsets and tables after a hash is cracked
+    pub all_preimages: bool,
+    #[clap(short = 'c', long)]
+    /// File caching candidate tails of hashes, reused by later lookups in tables of same parameters
+    pub tail_cache: Option<String>,
+    #[clap(long)]
+    /// Lengths to brute force after tables (e.g. 1-4), skipping those covered by tables
+    pub brute_length: Option<String>,
+    #[clap(long)]
+    /// Charset to brute force, each UTF-8 code point is an element (default: charset of tables)
+    pub brute_charset: Option<String>,
+    #[clap(long)]
+    /// Hashcat-style masks to brute force instead of charset and lengths
+    pub brute_mask: Vec<String>,
+    #[clap(long)]
+    /// Range of indices in the brute-force space to enumerate (e.g. 0-1000000), for splitting work
+    pub brute_range: Option<String>,
 }
 
-fn read_rainbow_table(table: &mut File) -> (RainbowTableHeader, Vec<u8>) {
-    let mut header: RainbowTableHeader = unsafe { std::mem::zeroed() };
+#[derive(Serialize)]
+struct HashRecord<'a> {
+    user: Option<&'a str>,
+    hash: String,
+    status: &'static str,
+    plaintexts: &'a [Crack],
+    false_alarms: usize,
+    time_ms: Option<f64>,
+    coverage: &'a [Coverage],
+}
 
-    unsafe {
-        let header_ptr = std::slice::from_raw_parts_mut(
-            &mut header as *mut _ as *mut u8,
-            std::mem::size_of::<RainbowTableHeader>(),
-        );
-        table
-            .read_exact(header_ptr)
-            .expect("Cannot read header from table");
+#[derive(Serialize)]
+struct Summary {
+    total: usize,
+    cracked: usize,
+    from_potfile: usize,
+    failed: usize,
+    incomplete: usize,
+    skipped: usize,
+    false_alarms: usize,
+    time_ms: f64,
+}
+
+// what is searched and found in a group of tables
+#[derive(Serialize)]
+struct GroupReport {
+    group: usize,
+    description: String,
+    tables: Vec<String>,
+    searched: usize,
+    cracked: usize,
+    false_alarms: usize,
+    time_ms: f64,
+}
+
+// one line of json output
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum Record<'a> {
+    Hash(HashRecord<'a>),
+    Group(&'a GroupReport),
+    Summary(Summary),
+}
+
+fn to_ms(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
     }
+}
 
-    assert!(header.is_valid());
+fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-    let mut charset: Vec<u8> = Vec::new();
-    charset.resize(header.charset_length as usize, 0);
-    table
-        .read_exact(charset.as_mut_slice())
-        .expect("Cannot read charset from table");
+fn opt_to_string<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
 
-    info!(
-        "Table header: {:?}, charset: {}",
-        header,
-        String::from_utf8_lossy(&charset).to_owned()
-    );
+// print result of each target in input order, followed by results of each group and a summary
+fn print_results(
+    format: &str,
+    targets: &[Target],
+    results: &HashMap<String, HashResult>,
+    groups: &[GroupReport],
+    skipped: usize,
+    elapsed: Duration,
+) {
+    let count_status = |status: &str| {
+        targets
+            .iter()
+            .filter(|t| results[&t.hash_str()].status() == status)
+            .count()
+    };
+    let summary = Summary {
+        total: targets.len(),
+        cracked: count_status("cracked"),
+        from_potfile: count_status("potfile"),
+        failed: count_status("failed"),
+        incomplete: count_status("incomplete"),
+        skipped,
+        false_alarms: results.values().map(|r| r.false_alarms).sum(),
+        time_ms: to_ms(elapsed),
+    };
+
+    match format {
+        "json" => {
+            for t in targets {
+                let result = &results[&t.hash_str()];
+                let record = Record::Hash(HashRecord {
+                    user: t.user.as_deref(),
+                    hash: t.hash_str(),
+                    status: result.status(),
+                    plaintexts: &result.cracks,
+                    false_alarms: result.false_alarms,
+                    time_ms: result.time.map(to_ms),
+                    coverage: &result.coverage,
+                });
+                println!("{}", serde_json::to_string(&record).unwrap());
+            }
+            for g in groups {
+                println!("{}", serde_json::to_string(&Record::Group(g)).unwrap());
+            }
+            println!(
+                "{}",
+                serde_json::to_string(&Record::Summary(summary)).unwrap()
+            );
+        }
+        "csv" => {
+            println!("record,user,hash,status,plain,table,chain,pos,false_alarms,time_ms,searched");
+            for t in targets {
+                let result = &results[&t.hash_str()];
+                let searched = result.searched();
+                let row = |crack: Option<&Crack>| {
+                    csv_row(&[
+                        "hash".to_owned(),
+                        opt_to_string(&t.user),
+                        t.hash_str(),
+                        result.status().to_owned(),
+                        crack.map(|c| encode_plain(&c.plain)).unwrap_or_default(),
+                        crack.map(|c| opt_to_string(&c.table)).unwrap_or_default(),
+                        crack.map(|c| opt_to_string(&c.chain)).unwrap_or_default(),
+                        crack.map(|c| opt_to_string(&c.pos)).unwrap_or_default(),
+                        result.false_alarms.to_string(),
+                        opt_to_string(&result.time.map(to_ms)),
+                        format!("{}/{}", searched.0, searched.1),
+                    ])
+                };
+                if result.cracks.is_empty() {
+                    println!("{}", row(None));
+                }
+                for crack in &result.cracks {
+                    println!("{}", row(Some(crack)));
+                }
+            }
+            // group parameters are put in the table column and counts in the status column
+            for g in groups {
+                println!(
+                    "{}",
+                    csv_row(&[
+                        "group".to_owned(),
+                        String::new(),
+                        String::new(),
+                        format!("searched={};cracked={}", g.searched, g.cracked),
+                        String::new(),
+                        format!("{}: {}", g.group, g.description),
+                        String::new(),
+                        String::new(),
+                        g.false_alarms.to_string(),
+                        g.time_ms.to_string(),
+                        String::new(),
+                    ])
+                );
+            }
+            // totals are put in the status column
+            println!(
+                "{}",
+                csv_row(&[
+                    "summary".to_owned(),
+                    String::new(),
+                    String::new(),
+                    format!(
+                        "total={};cracked={};from_potfile={};failed={};incomplete={};skipped={}",
+                        summary.total,
+                        summary.cracked,
+                        summary.from_potfile,
+                        summary.failed,
+                        summary.incomplete,
+                        summary.skipped
+                    ),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    summary.false_alarms.to_string(),
+                    summary.time_ms.to_string(),
+                    String::new(),
+                ])
+            );
+        }
+        _ => {
+            for t in targets {
+                let result = &results[&t.hash_str()];
+                let all_plain_text: Vec<String> =
+                    result.plains().iter().map(|p| encode_plain(p)).collect();
+                if all_plain_text.is_empty() && !result.is_complete() {
+                    let (searched, total) = result.searched();
+                    println!(
+                        "Plain text not found within budget for {}, searched {}/{} chain offsets",
+                        t.label(),
+                        searched,
+                        total
+                    );
+                    for c in result.coverage.iter().filter(|c| !c.is_complete()) {
+                        println!("  {}: {}/{}", c.table, c.offsets, c.chain_len);
+                    }
+                } else if all_plain_text.is_empty() {
+                    error!("Failed to find plain text for {}", t.label());
+                    println!("Failed to find plain text for {}", t.label());
+                } else {
+                    println!("Found plain text for {}: {:?}", t.label(), all_plain_text);
+                }
+            }
+            for g in groups {
+                println!(
+                    "Group {} ({}): cracked {}/{} hashes, {} false alarms, {:.3}s elapsed",
+                    g.group,
+                    g.description,
+                    g.cracked,
+                    g.searched,
+                    g.false_alarms,
+                    g.time_ms / 1000.0
+                );
+            }
+            println!(
+                "Cracked {}/{} hashes ({} from potfile), {} false alarms, {:.3}s elapsed",
+                summary.cracked + summary.from_potfile,
+                summary.total,
+                summary.from_potfile,
+                summary.false_alarms,
+                elapsed.as_secs_f64()
+            );
+            if summary.incomplete > 0 {
+                println!("{} hashes not found within budget", summary.incomplete);
+            }
+        }
+    }
+}
 
-    (header, charset)
+fn new_progress_bar(len: u64) -> ProgressBar {
+    let progress = ProgressBar::new(len);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed}/{eta}] [{bar:50.cyan/blue}] {pos}/{len} ({percent}%)",
+            )
+            .progress_chars("#>-"),
+    );
+    progress
 }
 
-fn run_lookup(opts: &LookupOptions) -> HashMap<String, Vec<String>> {
-    let mut initialized = false;
-    let mut header: RainbowTableHeader = unsafe { std::mem::zeroed() };
-    let mut charset: Vec<u8> = Vec::new();
-    let mut mapped_tables: Vec<(String, Mmap)> = Vec::new();
-
-    // open all tables and check header
-    for f in &opts.table_files {
-        info!("Opening rainbow table: {}", &f);
-        if !Path::exists(Path::new(&f)) {
-            error!("File not exists: {}", &f);
-            std::process::exit(1);
+// collect targets from arguments and hash files, report malformed ones
+fn load_targets(opts: &LookupOptions) -> (Vec<Target>, usize) {
+    let mut targets = Vec::new();
+    let mut malformed = 0;
+
+    for hash_str in &opts.hash {
+        match Target::parse(hash_str) {
+            Ok(t) => targets.push(t),
+            Err(e) => {
+                warn!("Skipping argument {}: {}", hash_str, e);
+                malformed += 1;
+            }
         }
-        // read header from files
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(&f)
-            .expect(format!("Cannot open table file: {}", &f).as_str());
-        let mut read_result = read_rainbow_table(&mut file);
-        // check header consistency
-        if !initialized {
-            header = read_result.0;
-            charset.append(&mut read_result.1);
-            initialized = true;
-        } else if header != read_result.0 || &charset != &read_result.1 {
-            error!("Table {} has inconsistent parameters, abort", &f);
-            std::process::exit(1);
+    }
+
+    for f in &opts.hash_file {
+        let read_result = if f == "-" {
+            read_targets(std::io::stdin().lock())
+        } else {
+            File::open(f).and_then(|file| read_targets(BufReader::new(file)))
+        };
+        let (mut file_targets, file_malformed) = read_result.unwrap_or_else(|e| {
+            error!("Cannot read hash file {}: {}", f, e);
+            std::process::exit(Error::Io(e).exit_code());
+        });
+        info!("Read {} hashes from {}", file_targets.len(), f);
+        for (line, e) in &file_malformed {
+            warn!("Skipping line {} of {}: {}", line, f, e);
         }
-        // mmap file
-        mapped_tables.push((f.to_owned(), unsafe {
-            MmapOptions::new().map(&file).expect("Failed to mmap file")
-        }));
+        malformed += file_malformed.len();
+        targets.append(&mut file_targets);
+    }
+
+    // rainbow tables only cover unsalted hashes
+    let salted = targets.iter().filter(|t| t.salt.is_some()).count();
+    if salted > 0 {
+        warn!("Skipping {} salted hashes", salted);
+        targets.retain(|t| t.salt.is_none());
+    }
+
+    if malformed + salted > 0 {
+        eprintln!(
+            "Skipped {} malformed and {} salted hashes",
+            malformed, salted
+        );
+    }
+    if targets.is_empty() {
+        error!("No valid hash to crack");
+        std::process::exit(1);
     }
+    (targets, malformed + salted)
+}
+
+// search hashes in one table set, return what is found for each hash
+fn search_set(
+    opts: &LookupOptions,
+    table_set: &TableSet,
+    target_hashes: &[[u8; 32]],
+    budget: &Budget,
+) -> Vec<HashResult> {
+    let chain_len = table_set.header().chain_len as usize;
+    let mut all_results: Vec<HashResult> = vec![HashResult::default(); target_hashes.len()];
+
+    if opts.batch {
+        info!("Trying to crack {} hashes in batch\n", target_hashes.len());
+        let progress = new_progress_bar((target_hashes.len() * chain_len) as u64);
+
+        // one pass over each table for all hashes
+        for table in &table_set.tables {
+            // in first-hit mode, cracked hashes are not searched in later tables
+            let remaining: Vec<usize> = (0..target_hashes.len())
+                .filter(|&i| opts.all_preimages || all_results[i].cracks.is_empty())
+                .collect();
+            if remaining.is_empty() {
+                break;
+            }
+            let remaining_hashes: Vec<[u8; 32]> =
+                remaining.iter().map(|&i| target_hashes[i]).collect();
+            info!(
+                "Starting searching {} hashes in {}\n",
+                remaining.len(),
+                table.name()
+            );
+            progress.reset();
+            progress.set_length((remaining.len() * chain_len) as u64);
+
+            let cracked = table_set.search_batch(table, &remaining_hashes, budget, &progress);
 
-    // calculate offset to rainbow chain data
-    let padding_len = if charset.len() % 8 != 0 {
-        8 - charset.len() % 8
+            info!(
+                "Cracked {} hashes in table {}\n",
+                cracked.iter().filter(|c| !c.cracks.is_empty()).count(),
+                table.name()
+            );
+            for (&i, c) in remaining.iter().zip(cracked) {
+                all_results[i].merge(c);
+            }
+        }
+        progress.finish();
     } else {
-        0
+        // run on each hash
+        for (target_hash, all_result) in target_hashes.iter().zip(all_results.iter_mut()) {
+            let start = Instant::now();
+            info!("Trying to crack {}\n", hex::encode(target_hash));
+            let hash_budget = budget.limit(opts.hash_timeout.map(Duration::from_secs_f64));
+
+            // show progress bar
+            let progress = new_progress_bar(chain_len as u64);
+
+            // walk offsets of all tables together from the cheapest one
+            let cracked = table_set.search_all(target_hash, &hash_budget, &progress);
+            info!("Plain text found: {:?}\n", cracked);
+            all_result.merge(cracked);
+
+            progress.finish();
+            all_result.time = Some(start.elapsed());
+        }
+    }
+
+    if let Some(cache) = &table_set.tail_cache {
+        info!("{} candidate tails taken from tail cache", cache.hits());
+        if let Err(e) = cache.save() {
+            error!("Cannot write tail cache: {}", e);
+        }
+    }
+
+    all_results
+}
+
+// table sets to search: those given by -t, or all groups found in the catalog directory
+fn open_table_sets(opts: &LookupOptions) -> Vec<TableSet> {
+    let wordlists: Vec<_> = opts
+        .wordlist
+        .iter()
+        .map(|f| {
+            Arc::new(Wordlist::load(f).unwrap_or_else(|e| {
+                error!("Cannot read wordlist {}: {}", f, e);
+                std::process::exit(Error::Io(e).exit_code());
+            }))
+        })
+        .collect();
+    let mut table_sets = match &opts.catalog {
+        Some(dir) => {
+            let catalog = Catalog::scan(dir, &wordlists).unwrap_or_else(|e| {
+                error!("Cannot scan table directory {}: {}", dir, e);
+                std::process::exit(e.exit_code());
+            });
+            if catalog.groups.is_empty() {
+                error!("No usable rainbow table in {}", dir);
+                std::process::exit(1);
+            }
+            catalog.groups
+        }
+        None => vec![
+            TableSet::open(&opts.table_files, &wordlists).unwrap_or_else(|e| {
+                error!("Cannot open rainbow tables: {}", e);
+                std::process::exit(e.exit_code());
+            }),
+        ],
     };
-    let data_offset = std::mem::size_of::<RainbowTableHeader>() + padding_len + charset.len();
-    info!("Data offset of tables: {}", data_offset);
-
-    // calculate parameters
-    let plaintext_len_range = (header.min_length as usize)..(header.max_length + 1) as usize;
-    let plaintext_lens = util::generate_cumulative_lengths(&plaintext_len_range, charset.len());
-    let chain_len = header.chain_len as usize;
-    let num_chain = header.num_chain as usize;
-    let plaintext_space_size = plaintext_lens[plaintext_len_range.end - 1];
+    for table_set in &mut table_sets {
+        table_set.all_preimages = opts.all_preimages;
+        // groups of different parameters keep separate records in the same file
+        if let Some(f) = &opts.tail_cache {
+            table_set.open_tail_cache(f).unwrap_or_else(|e| {
+                error!("Cannot read tail cache {}: {}", f, e);
+                std::process::exit(e.exit_code());
+            });
+        }
+    }
+    table_sets
+}
+
+// search hashes not cracked yet (all hashes if all_preimages is set) in a group, merging the results
+fn search_group<F>(
+    opts: &LookupOptions,
+    description: String,
+    tables: Vec<String>,
+    target_hashes: &[[u8; 32]],
+    all_results: &mut [HashResult],
+    groups: &mut Vec<GroupReport>,
+    search: F,
+) where
+    F: FnOnce(&[[u8; 32]]) -> Vec<HashResult>,
+{
+    let remaining: Vec<usize> = (0..target_hashes.len())
+        .filter(|&i| opts.all_preimages || all_results[i].cracks.is_empty())
+        .collect();
+    let start = Instant::now();
     info!(
-        "Plain text count: {:?}, space size: {}",
-        plaintext_lens, plaintext_space_size
+        "Searching {} hashes in group {} ({})",
+        remaining.len(),
+        groups.len(),
+        description
     );
+    let remaining_hashes: Vec<[u8; 32]> = remaining.iter().map(|&i| target_hashes[i]).collect();
+    let results = if remaining.is_empty() {
+        Vec::new()
+    } else {
+        search(&remaining_hashes)
+    };
 
-    let mut results: HashMap<String, Vec<String>> = HashMap::new();
+    groups.push(GroupReport {
+        group: groups.len(),
+        description,
+        tables,
+        searched: remaining.len(),
+        cracked: results.iter().filter(|r| !r.cracks.is_empty()).count(),
+        false_alarms: results.iter().map(|r| r.false_alarms).sum(),
+        time_ms: to_ms(start.elapsed()),
+    });
+    for (&i, result) in remaining.iter().zip(results) {
+        let time = match (all_results[i].time, result.time) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        all_results[i].merge(result);
+        all_results[i].time = time;
+    }
+}
 
-    // run on each hash str
-    for hash_str in &opts.hash {
-        let mut target_hash = [0u8; 32];
-        hex::decode_to_slice(hash_str, &mut target_hash).expect("Hash not valid");
-        info!("Trying to crack {}\n", &hash_str);
-
-        // show progress bar
-        let progress = ProgressBar::new(chain_len as u64);
-        progress.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "{spinner:.green} [{elapsed}/{eta}] [{bar:50.cyan/blue}] {pos}/{len} ({percent}%)",
-                )
-                .progress_chars("#>-"),
-        );
+// plain text space to brute force, without segments covered by tables
+fn brute_force_space(opts: &LookupOptions, table_sets: &[TableSet]) -> Option<PlaintextSpace> {
+    let space = if !opts.brute_mask.is_empty() {
+        PlaintextSpace::from_masks(&opts.brute_mask, &[])
+    } else if let Some(lengths) = &opts.brute_length {
+        let (min_length, max_length) = parse_range(lengths).unwrap_or_else(|| {
+            error!("Invalid brute-force lengths {}, expecting MIN-MAX", lengths);
+            std::process::exit(1);
+        });
+        // charset of the first table set defined by one
+        let charset = match &opts.brute_charset {
+            Some(chars) => Charset::from_chars(chars),
+            None => table_sets
+                .iter()
+                .filter(|set| set.header().space_length == 0)
+                .find_map(|set| Charset::decode(&set.tables[0].charset).ok())
+                .unwrap_or_else(|| {
+                    error!("No charset for brute force given by --brute-charset or tables");
+                    std::process::exit(1);
+                }),
+        };
+        if charset.is_empty() || min_length == 0 {
+            error!("Brute-force charset and lengths must not be empty");
+            std::process::exit(1);
+        }
+        PlaintextSpace::from_charset(&charset, &(min_length as usize..max_length as usize + 1))
+    } else {
+        return None;
+    };
+    let space = space.unwrap_or_else(|e| {
+        error!("Invalid brute-force space: {}", e);
+        std::process::exit(1);
+    });
+    let covered: Vec<&PlaintextSpace> = table_sets.iter().map(|set| &set.space).collect();
+    let uncovered = uncovered_space(&space, &covered);
+    if uncovered.is_none() {
+        info!("Brute-force space is covered by tables");
+    }
+    uncovered
+}
 
-        // store cracked plain text
-        let mut all_plain_text: Vec<String> = Vec::new();
+// MIN-MAX as a pair of numbers
+fn parse_range(s: &str) -> Option<(u128, u128)> {
+    let mut parts = s.splitn(2, '-');
+    let min = parts.next()?.trim().parse().ok()?;
+    let max = parts.next()?.trim().parse().ok()?;
+    if min <= max {
+        Some((min, max))
+    } else {
+        None
+    }
+}
 
-        // iterate over each table
-        for m in &mapped_tables {
-            let filename = &m.0;
-            info!("Starting searching in {}\n", &filename);
+// search hashes in all table sets and then by brute force,
+// return what is found for each hash and each group
+fn search_hashes(
+    opts: &LookupOptions,
+    hash_strs: &[String],
+) -> (Vec<HashResult>, Vec<GroupReport>) {
+    let table_sets = if opts.table_files.is_empty() && opts.catalog.is_none() {
+        Vec::new()
+    } else {
+        open_table_sets(opts)
+    };
+    let brute_space = brute_force_space(opts, &table_sets);
 
-            // cast data to &[RainbowChain]
-            let chain_data = &m.1.as_ref()[data_offset..];
-            let chains = unsafe {
-                std::slice::from_raw_parts(chain_data.as_ptr() as *const RainbowChain, num_chain)
-            };
+    // stop early on timeout or Ctrl-C, reporting what is searched so far
+    let budget = Budget::new(opts.timeout.map(Duration::from_secs_f64));
+    let handler_budget = budget.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_budget.cancel()) {
+        warn!("Cannot set Ctrl-C handler: {}", e);
+    }
+    if opts.batch && opts.hash_timeout.is_some() {
+        warn!("Per-hash timeout is ignored in batch mode");
+    }
 
-            progress.reset();
+    let target_hashes: Vec<[u8; 32]> = hash_strs
+        .iter()
+        .map(|hash_str| {
+            let mut target_hash = [0u8; 32];
+            hex::decode_to_slice(hash_str, &mut target_hash).unwrap();
+            target_hash
+        })
+        .collect();
+
+    let mut all_results: Vec<HashResult> = vec![HashResult::default(); target_hashes.len()];
+    let mut groups = Vec::new();
+
+    // in first-hit mode, hashes cracked in a group are not searched in later ones
+    for table_set in &table_sets {
+        search_group(
+            opts,
+            table_set.description(),
+            table_set.tables.iter().map(|t| t.name()).collect(),
+            &target_hashes,
+            &mut all_results,
+            &mut groups,
+            |hashes| search_set(opts, table_set, hashes, &budget),
+        );
+    }
 
-            // find crack
-            let mut cracked: Vec<_> = (0..chain_len)
-                .into_par_iter()
-                .map(|i| {
-                    progress.inc(1);
-                    // offset on chain
-                    let chain_offset = chain_len - 1 - i;
-                    // first step: R_offset
-                    let mut target_tail = RainbowIndex::from_hash(
-                        &target_hash,
-                        0,
-                        *plaintext_lens.last().unwrap(),
-                        chain_offset as u32,
-                    );
-                    // remaining steps: H, R_{o+1}, H, ..., R_{l-1}
-                    if i > 0 {
-                        target_tail = RainbowIndex::traverse_chain(
-                            target_tail,
-                            charset.as_slice(),
-                            &plaintext_len_range,
-                            &plaintext_lens,
-                            chain_offset + 1,
-                            i,
-                            0,
-                            |_, _, _| false,
-                        );
-                    }
-                    debug!(
-                        "Searching for step {} with target tail {:#018x}\n",
-                        i, target_tail.0
-                    );
+    if let Some(space) = brute_space {
+        let (from, to) = match &opts.brute_range {
+            Some(range) => parse_range(range).unwrap_or_else(|| {
+                error!("Invalid brute-force range {}, expecting FROM-TO", range);
+                std::process::exit(1);
+            }),
+            None => (0, space.size()),
+        };
+        search_group(
+            opts,
+            format!("brute force of {} plain texts", to.saturating_sub(from)),
+            vec![BRUTE_FORCE_NAME.to_owned()],
+            &target_hashes,
+            &mut all_results,
+            &mut groups,
+            |hashes| {
+                let progress = new_progress_bar(to.saturating_sub(from) as u64);
+                let results = brute_force(
+                    &space,
+                    from..to,
+                    hashes,
+                    opts.all_preimages,
+                    &budget,
+                    &progress,
+                )
+                .unwrap_or_else(|e| {
+                    error!("Cannot brute force: {}", e);
+                    std::process::exit(e.exit_code());
+                });
+                progress.finish();
+                results
+            },
+        );
+    }
 
-                    let result = match chains.binary_search(&RainbowChain {
-                        head: RainbowIndex(0),
-                        tail: target_tail,
-                    }) {
-                        Ok(match_idx) => {
-                            let match_chain = &chains[match_idx];
-                            debug!(
-                                "Found matching chain {} on step {}: {:?}\n",
-                                match_idx, i, match_chain
-                            );
-                            match match_chain.find_match(
-                                &target_hash,
-                                charset.as_slice(),
-                                &plaintext_len_range,
-                                plaintext_lens.as_ref(),
-                                chain_len as usize,
-                                0,
-                            ) {
-                                Some(result) => {
-                                    let plain = String::from_utf8_lossy(&result).into_owned();
-                                    debug!("Found plain text: {:?}\n", plain);
-                                    Some(plain)
-                                }
-                                None => {
-                                    debug!("False alarm detected\n");
-                                    None
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            debug!("Target tail not found for step {}\n", i);
-                            None
-                        }
-                    };
-
-                    result
-                })
-                .filter(|r| !r.is_none())
-                .map(|r| r.unwrap())
-                .collect();
+    (all_results, groups)
+}
 
-            info!("Plain text found in table {}: {:?}\n", &filename, &cracked);
-            all_plain_text.append(&mut cracked);
+fn run_lookup(opts: &LookupOptions) -> HashMap<String, Vec<Vec<u8>>> {
+    let start = Instant::now();
+    let (targets, skipped) = load_targets(opts);
+    let mut potfile = opts.potfile.as_ref().map(|f| {
+        Potfile::load(f).unwrap_or_else(|e| {
+            error!("Cannot read potfile {}: {}", f, e);
+            std::process::exit(Error::Io(e).exit_code());
+        })
+    });
+
+    // list cracked or uncracked hashes without touching tables
+    if opts.show || opts.left {
+        let potfile = potfile.as_ref().unwrap_or_else(|| {
+            error!("--show and --left require a potfile");
+            std::process::exit(1);
+        });
+        let mut results: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+        for t in &targets {
+            let plains = potfile.get(&t.hash_str()).cloned().unwrap_or_default();
+            if opts.show {
+                for plain in &plains {
+                    println!("{}:{}", t.line(), encode_plain(plain));
+                }
+            } else if plains.is_empty() {
+                println!("{}", t.line());
+            }
+            results.insert(t.hash_str(), plains);
         }
+        return results;
+    }
 
-        // post precessing
-        progress.finish();
-        all_plain_text.sort();
-        all_plain_text.dedup();
-
-        if all_plain_text.is_empty() {
-            error!("Failed to find plain text for {}", &hash_str);
-            println!("Failed to find plain text for {}", &hash_str);
-        } else {
-            println!("Found plain text for {}: {:?}", &hash_str, &all_plain_text);
+    // look up each distinct hash once, skipping those already in potfile
+    let mut results: HashMap<String, HashResult> = HashMap::new();
+    let mut hash_strs: Vec<String> = Vec::new();
+    for t in &targets {
+        let hash_str = t.hash_str();
+        match potfile.as_ref().and_then(|p| p.get(&hash_str)) {
+            Some(plains) => {
+                results.insert(hash_str, HashResult::from_potfile(plains));
+            }
+            None => hash_strs.push(hash_str),
         }
+    }
+    hash_strs.sort();
+    hash_strs.dedup();
+    info!(
+        "{} hashes found in potfile, {} to look up",
+        results.len(),
+        hash_strs.len()
+    );
 
-        results.insert(hash_str.clone(), all_plain_text);
+    let mut groups = Vec::new();
+    if !hash_strs.is_empty() {
+        let brute = opts.brute_length.is_some() || !opts.brute_mask.is_empty();
+        if !opts.table_files.is_empty() && opts.catalog.is_some() {
+            error!("Rainbow tables (-t) and a table directory (-d) cannot be used together");
+            std::process::exit(1);
+        }
+        if opts.table_files.is_empty() && opts.catalog.is_none() && !brute {
+            error!("No rainbow table (-t), table directory (-d) or brute-force space given");
+            std::process::exit(1);
+        }
+        let (all_results, group_reports) = search_hashes(opts, &hash_strs);
+        // only a catalog or brute force gives more than one group to report
+        if opts.catalog.is_some() || brute {
+            groups = group_reports;
+        }
+        for (hash_str, mut result) in hash_strs.into_iter().zip(all_results) {
+            result.dedup();
+            // save new cracks
+            if let Some(potfile) = potfile.as_mut() {
+                potfile
+                    .append(&hash_str, &result.plains())
+                    .unwrap_or_else(|e| error!("Cannot write potfile: {}", e));
+            }
+            results.insert(hash_str, result);
+        }
     }
 
+    print_results(
+        &opts.format,
+        &targets,
+        &results,
+        &groups,
+        skipped,
+        start.elapsed(),
+    );
+
     results
+        .into_iter()
+        .map(|(hash_str, result)| (hash_str, result.plains()))
+        .collect()
 }
 
 fn main() {
     env_logger::builder().init();
     let opts: LookupOptions = LookupOptions::parse();
-    println!("Program options: {:?}", opts);
+    // keep machine-readable output clean
+    if opts.format == "plain" {
+        println!("Program options: {:?}", opts);
+    } else {
+        info!("Program options: {:?}", opts);
+    }
     run_lookup(&opts);
 }
 
@@ -254,13 +803,33 @@ mod tests {
     use super::*;
     use rand::Rng;
     use sm3::my_sm3_impl::my_hash_impl_inplace;
+    use sm3::rainbow::RainbowIndex;
+    use sm3::table::RainbowTable;
 
+    // needs tables in the working directory, see tests/coverage_tests.rs for a self-contained test
     #[test]
+    #[ignore]
     fn test_coverage() {
         env_logger::builder().init();
         let mut test_options = LookupOptions {
             hash: Vec::new(),
+            hash_file: Vec::new(),
             table_files: Vec::new(),
+            catalog: None,
+            wordlist: Vec::new(),
+            batch: true,
+            potfile: None,
+            show: false,
+            left: false,
+            format: "plain".to_owned(),
+            timeout: None,
+            hash_timeout: None,
+            all_preimages: false,
+            tail_cache: None,
+            brute_length: None,
+            brute_charset: None,
+            brute_mask: Vec::new(),
+            brute_range: None,
         };
 
         // find all .dat files
@@ -277,34 +846,25 @@ mod tests {
         }
 
         // read parameters
-        let read_result =
-            read_rainbow_table(&mut File::open(Path::new(&test_options.table_files[0])).unwrap());
-        let header = &read_result.0;
-        let charset = &read_result.1;
-        let plaintext_len_range = (header.min_length as usize)..(header.max_length + 1) as usize;
-        let plaintext_lens = util::generate_cumulative_lengths(&plaintext_len_range, charset.len());
-        let plaintext_space_size = plaintext_lens[plaintext_len_range.end - 1];
+        let table = RainbowTable::open(&test_options.table_files[0]).unwrap();
+        let space = table.plaintext_space(&[]).unwrap();
+        let plaintext_space_size = space.size();
 
         let mut rng = rand::thread_rng();
         let mut hash = [0u8; 32];
         let mut plaintext: Vec<u8> = Vec::new();
-        plaintext.resize(plaintext_len_range.end - 1, 0);
+        plaintext.resize(space.max_len(), 0);
 
         let hash_count = 1000;
 
         // generate some hashes according to parameters
         for _ in 0..hash_count {
             let index = rng.gen_range(0..plaintext_space_size);
-            let len = RainbowIndex(index).to_plaintext(
-                charset,
-                &plaintext_len_range,
-                &plaintext_lens,
-                plaintext.as_mut_slice(),
-            );
+            let len = RainbowIndex::<u128>(index).to_plaintext(&space, plaintext.as_mut_slice());
             my_hash_impl_inplace(&plaintext, len as usize, &mut hash);
             &test_options.hash.push(hex::encode(hash));
         }
-        std::mem::drop(read_result);
+        std::mem::drop(table);
 
         info!("Generated {} SM3 hashes", hash_count);
 
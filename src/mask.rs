@@ -0,0 +1,120 @@
+// hashcat-style masks, e.g. "?u?l?l?l?l?l?d?d" (or "?u?l{5}?d{2}")
+// reference: https://hashcat.net/wiki/doku.php?id=mask_attack
+
+use std::collections::HashSet;
+
+use crate::rainbow::Charset;
+
+pub const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+pub const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub const DIGIT: &[u8] = b"0123456789";
+pub const HEX_LOWER: &[u8] = b"0123456789abcdef";
+pub const HEX_UPPER: &[u8] = b"0123456789ABCDEF";
+pub const SPECIAL: &[u8] = b" !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+pub const MAX_CUSTOM_CHARSETS: usize = 4;
+
+// charset of a built-in placeholder (?l, ?u, ...)
+fn builtin_charset(c: char) -> Option<Charset> {
+    let bytes = match c {
+        'l' => LOWER.to_vec(),
+        'u' => UPPER.to_vec(),
+        'd' => DIGIT.to_vec(),
+        'h' => HEX_LOWER.to_vec(),
+        'H' => HEX_UPPER.to_vec(),
+        's' => SPECIAL.to_vec(),
+        'a' => [LOWER, UPPER, DIGIT, SPECIAL].concat(),
+        'b' => (0..=255).collect(),
+        _ => return None,
+    };
+    Some(Charset::from_bytes(&bytes))
+}
+
+// expand a custom charset definition (may contain built-in placeholders),
+// every other UTF-8 code point is an element
+pub fn parse_custom_charset(def: &str) -> Result<Charset, String> {
+    let mut elements = Vec::new();
+    let mut chars = def.chars();
+    while let Some(c) = chars.next() {
+        if c == '?' {
+            let c = chars
+                .next()
+                .ok_or_else(|| "Custom charset ends with a single '?'".to_owned())?;
+            match c {
+                '?' => elements.push(b"?".to_vec()),
+                _ => elements.append(
+                    &mut builtin_charset(c)
+                        .ok_or_else(|| format!("Unknown placeholder in charset: ?{}", c))?
+                        .elements,
+                ),
+            }
+        } else {
+            elements.push(c.to_string().into_bytes());
+        }
+    }
+    // remove duplicated elements but keep the order
+    let mut seen = HashSet::new();
+    elements.retain(|e| seen.insert(e.clone()));
+    if elements.is_empty() {
+        return Err("Custom charset is empty".to_owned());
+    }
+    Ok(Charset { elements })
+}
+
+// parse a mask to the charset of each position, literal characters become single-element charsets
+pub fn parse_mask(mask: &str, custom_charsets: &[Option<String>]) -> Result<Vec<Charset>, String> {
+    let custom: Vec<Option<Charset>> = custom_charsets
+        .iter()
+        .map(|c| c.as_ref().map(|def| parse_custom_charset(def)).transpose())
+        .collect::<Result<_, _>>()?;
+
+    let mut positions: Vec<Charset> = Vec::new();
+    let mut chars = mask.chars();
+    while let Some(c) = chars.next() {
+        if c == '?' {
+            let c = chars
+                .next()
+                .ok_or_else(|| "Mask ends with a single '?'".to_owned())?;
+            let charset = match c {
+                '?' => Charset::from_bytes(b"?"),
+                '1'..='9' => {
+                    let n = c as usize - '1' as usize;
+                    if n >= MAX_CUSTOM_CHARSETS {
+                        return Err(format!("Unknown custom charset: ?{}", c));
+                    }
+                    custom
+                        .get(n)
+                        .cloned()
+                        .flatten()
+                        .ok_or_else(|| format!("Custom charset ?{} is not defined", c))?
+                }
+                _ => builtin_charset(c)
+                    .ok_or_else(|| format!("Unknown placeholder in mask: ?{}", c))?,
+            };
+            positions.push(charset);
+        } else if c == '{' && !positions.is_empty() {
+            // repeat last position, e.g. ?d{6}
+            let rest = chars.as_str();
+            let end = rest
+                .find('}')
+                .ok_or_else(|| "Unclosed '{' in mask".to_owned())?;
+            let count: usize = rest[..end]
+                .parse()
+                .map_err(|_| "Invalid repetition count in mask".to_owned())?;
+            if count == 0 {
+                return Err("Repetition count in mask must be positive".to_owned());
+            }
+            let last = positions.last().unwrap().clone();
+            positions.resize(positions.len() + count - 1, last);
+            chars = rest[end + 1..].chars();
+        } else {
+            positions.push(Charset {
+                elements: vec![c.to_string().into_bytes()],
+            });
+        }
+    }
+    if positions.is_empty() {
+        return Err("Mask is empty".to_owned());
+    }
+    Ok(positions)
+}
@@ -0,0 +1,146 @@
+// free-form metadata of a rainbow table, stored after the chains so that
+// readers not knowing it just ignore the rest of the file
+
+use std::convert::TryInto;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+// "HCMETADT" (Harry Chen METADaTa) in little endian
+pub const RAINBOW_TABLE_METADATA_MAGIC: u64 = 0x54444154454d4348;
+
+// types of metadata records
+pub const META_GENERATOR: u32 = 1;
+pub const META_CREATED: u32 = 2;
+pub const META_HOST: u32 = 3;
+pub const META_DURATION_MS: u32 = 4;
+pub const META_CHAINS_GENERATED: u32 = 5;
+pub const META_TAG: u32 = 6;
+
+// section layout: magic, byte length of records, then records of
+// type (u32), byte length of value (u32) and value;
+// records of unknown types are skipped by readers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    // name and version of the generating program
+    pub generator: Option<String>,
+    // seconds since unix epoch
+    pub created: Option<u64>,
+    pub host: Option<String>,
+    pub duration_ms: Option<u64>,
+    // chains computed, including those dropped for duplicated tails
+    pub chains_generated: Option<u64>,
+    // user-defined key and value
+    pub tags: Vec<(String, String)>,
+    // records of unknown types, kept as is
+    pub unknown: Vec<(u32, Vec<u8>)>,
+}
+
+fn read_value<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if bytes.len() < len {
+        return Err("Metadata record truncated".to_owned());
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(value)
+}
+
+fn to_string(value: &[u8]) -> Result<String, String> {
+    String::from_utf8(value.to_vec()).map_err(|_| "Metadata text is not UTF-8".to_owned())
+}
+
+fn to_u64(value: &[u8]) -> Result<u64, String> {
+    value
+        .try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_| "Metadata number must be 8 bytes".to_owned())
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    // records in file order
+    fn records(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut records = Vec::new();
+        let text = |t: u32, s: &Option<String>| s.as_ref().map(|s| (t, s.as_bytes().to_vec()));
+        let number = |t: u32, n: Option<u64>| n.map(|n| (t, n.to_le_bytes().to_vec()));
+        records.extend(text(META_GENERATOR, &self.generator));
+        records.extend(number(META_CREATED, self.created));
+        records.extend(text(META_HOST, &self.host));
+        records.extend(number(META_DURATION_MS, self.duration_ms));
+        records.extend(number(META_CHAINS_GENERATED, self.chains_generated));
+        for (key, value) in &self.tags {
+            let mut tag = Vec::new();
+            tag.write_u32::<LittleEndian>(key.len() as u32).unwrap();
+            tag.extend_from_slice(key.as_bytes());
+            tag.extend_from_slice(value.as_bytes());
+            records.push((META_TAG, tag));
+        }
+        records.extend(self.unknown.iter().cloned());
+        records
+    }
+
+    // whole section including magic and length
+    pub fn encode(&self) -> Vec<u8> {
+        let mut records = Vec::new();
+        for (t, value) in self.records() {
+            records.write_u32::<LittleEndian>(t).unwrap();
+            records
+                .write_u32::<LittleEndian>(value.len() as u32)
+                .unwrap();
+            records.extend_from_slice(&value);
+        }
+        let mut section = Vec::new();
+        section
+            .write_u64::<LittleEndian>(RAINBOW_TABLE_METADATA_MAGIC)
+            .unwrap();
+        section
+            .write_u64::<LittleEndian>(records.len() as u64)
+            .unwrap();
+        section.extend_from_slice(&records);
+        section
+    }
+
+    // decode the bytes following the chains, nothing there gives empty metadata
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, String> {
+        let mut metadata = Metadata::default();
+        if bytes.len() < 8
+            || bytes.read_u64::<LittleEndian>().unwrap() != RAINBOW_TABLE_METADATA_MAGIC
+        {
+            return Ok(metadata);
+        }
+        let len = bytes
+            .read_u64::<LittleEndian>()
+            .map_err(|_| "Metadata section truncated".to_owned())? as usize;
+        let mut records = read_value(&mut bytes, len)?;
+        while !records.is_empty() {
+            let t = records
+                .read_u32::<LittleEndian>()
+                .map_err(|_| "Metadata record truncated".to_owned())?;
+            let len = records
+                .read_u32::<LittleEndian>()
+                .map_err(|_| "Metadata record truncated".to_owned())?
+                as usize;
+            let value = read_value(&mut records, len)?;
+            match t {
+                META_GENERATOR => metadata.generator = Some(to_string(value)?),
+                META_CREATED => metadata.created = Some(to_u64(value)?),
+                META_HOST => metadata.host = Some(to_string(value)?),
+                META_DURATION_MS => metadata.duration_ms = Some(to_u64(value)?),
+                META_CHAINS_GENERATED => metadata.chains_generated = Some(to_u64(value)?),
+                META_TAG => {
+                    let mut tag = value;
+                    let key_len = tag
+                        .read_u32::<LittleEndian>()
+                        .map_err(|_| "Metadata tag truncated".to_owned())?
+                        as usize;
+                    let key = to_string(read_value(&mut tag, key_len)?)?;
+                    metadata.tags.push((key, to_string(tag)?));
+                }
+                _ => metadata.unknown.push((t, value.to_vec())),
+            }
+        }
+        Ok(metadata)
+    }
+}
@@ -17,12 +17,66 @@ fn p_1(x: u32) -> u32 {
     x ^ ((x << 15) | (x >> 17)) ^ ((x << 23) | (x >> 9))
 }
 
+// 4.1.  Initial Value
+const IV: [u32; 8] = [
+    0x7380166f, 0x4914b2b9, 0x172442d7, 0xda8a0600, 0xa96f30bc, 0x163138aa, 0xe38dee4d, 0xb0fb0e4e,
+];
+
+// round i of compression function on state A..H, with W_i and W'_i
+#[inline(always)]
+fn round(i: usize, state: &mut [u32; 8], w: u32, w1: u32) {
+    let [a, b, c, d, e, f, g, h] = *state;
+    // 4.2.  Constants T_j
+    let tj: u32 = if i <= 15 { 0x79cc4519 } else { 0x7a879d8a };
+    let ss1 = a
+        .rotate_left(12)
+        .wrapping_add(e)
+        .wrapping_add(tj.rotate_left((i % 32) as u32))
+        .rotate_left(7);
+    let ss2 = ss1 ^ a.rotate_left(12);
+    // 4.3. Boolean Functions FF_j and GG_j
+    let tt1 = if i <= 15 {
+        a ^ b ^ c
+    } else {
+        (a & b) | (a & c) | (b & c)
+    }
+    .wrapping_add(d)
+    .wrapping_add(ss2)
+    .wrapping_add(w1);
+    let tt2 = if i <= 15 {
+        e ^ f ^ g
+    } else {
+        (e & f) | ((!e) & g)
+    }
+    .wrapping_add(h)
+    .wrapping_add(ss1)
+    .wrapping_add(w);
+    *state = [
+        tt1,
+        a,
+        b.rotate_left(9),
+        c,
+        p_0(tt2),
+        e,
+        f.rotate_left(19),
+        g,
+    ];
+}
+
+// 5.3.2.  Message Expansion Function ME, extending 16 words to w[16..67]
+#[inline(always)]
+fn expand(w: &mut [u32; 68]) {
+    for i in 16..68 {
+        // W_j = P_1(W_{j - 16} xor W_{j - 9} xor (W_{j - 3} <<< 15)) xor
+        // (W_{j - 13} <<< 7) xor W_{ j - 6 }
+        let temp: u32 = p_1(w[i - 16] ^ w[i - 9] ^ w[i - 3].rotate_left(15));
+        w[i] = temp ^ w[i - 13].rotate_left(7) ^ w[i - 6];
+    }
+}
+
 pub fn my_hash_impl_inplace(input: &[u8], input_len: usize, output: &mut [u8]) {
     #[allow(non_snake_case)]
-    let mut V: [u32; 8] = [
-        0x7380166f, 0x4914b2b9, 0x172442d7, 0xda8a0600, 0xa96f30bc, 0x163138aa, 0xe38dee4d,
-        0xb0fb0e4e,
-    ];
+    let mut V: [u32; 8] = IV;
 
     // preprocessing
     let length: u64 = u64::try_from(input_len).unwrap();
@@ -31,7 +85,7 @@ pub fn my_hash_impl_inplace(input: &[u8], input_len: usize, output: &mut [u8]) {
     // padding: 80 00 00 00 ... [64-bit length]
     let real_length = (input_len + 9 + 63) & (!63usize);
     let mut preprocessed: Vec<u8> = Vec::with_capacity(real_length);
-    preprocessed.extend_from_slice(input);
+    preprocessed.extend_from_slice(&input[..input_len]);
     preprocessed.resize(real_length, 0);
     preprocessed[input_len] = 0x80;
     // write length in big endian
@@ -52,14 +106,7 @@ pub fn my_hash_impl_inplace(input: &[u8], input_len: usize, output: &mut [u8]) {
                 .unwrap();
         }
 
-        // 5.3.2.  Message Expansion Function ME
-        // extend 16 words to w[16..67]
-        for i in 16..68 {
-            // W_j = P_1(W_{j - 16} xor W_{j - 9} xor (W_{j - 3} <<< 15)) xor
-            // (W_{j - 13} <<< 7) xor W_{ j - 6 }
-            let temp: u32 = p_1(w[i - 16] ^ w[i - 9] ^ ((w[i - 3] << 15) | (w[i - 3] >> 17)));
-            w[i] = temp ^ ((w[i - 13] << 7) | (w[i - 13] >> 25)) ^ w[i - 6];
-        }
+        expand(&mut w);
 
         for i in 0..64 {
             // W'_j = W_j xor W_{j + 4}
@@ -69,60 +116,13 @@ pub fn my_hash_impl_inplace(input: &[u8], input_len: usize, output: &mut [u8]) {
         // E_i = W_0 || ... || W_67 || W'_0 || ... || W'_63
 
         // 5.3.3. Compression Function CF
-        let mut a = V[0];
-        let mut b = V[1];
-        let mut c = V[2];
-        let mut d = V[3];
-        let mut e = V[4];
-        let mut f = V[5];
-        let mut g = V[6];
-        let mut h = V[7];
-
-        // compression main loop
+        let mut state = V;
         for i in 0..64 {
-            // 4.2.  Constants T_j
-            let tj: u32 = if i <= 15 { 0x79cc4519 } else { 0x7a879d8a };
-            let mut ss1: u32 = ((a << 12) | (a >> 20))
-                .wrapping_add(e)
-                .wrapping_add((tj << (i % 32)) | (tj.wrapping_shr(32 - i % 32)));
-            ss1 = (ss1 << 7) | (ss1 >> 25);
-            let ss2 = ss1 ^ ((a << 12) | (a >> 20));
-            // 4.3. Boolean Functions FF_j and GG_j
-            let tt1 = if i <= 15 {
-                a ^ b ^ c
-            } else {
-                (a & b) | (a & c) | (b & c)
-            }
-            .wrapping_add(d)
-            .wrapping_add(ss2)
-            .wrapping_add(w1[i as usize]);
-            let tt2 = if i <= 15 {
-                e ^ f ^ g
-            } else {
-                (e & f) | ((!e) & g)
-            }
-            .wrapping_add(h)
-            .wrapping_add(ss1)
-            .wrapping_add(w[i as usize]);
-
-            d = c;
-            c = (b << 9) | (b >> 23);
-            b = a;
-            a = tt1;
-            h = g;
-            g = (f << 19) | (f >> 13);
-            f = e;
-            e = p_0(tt2);
+            round(i, &mut state, w[i], w1[i]);
+        }
+        for i in 0..8 {
+            V[i] ^= state[i];
         }
-
-        V[0] ^= a;
-        V[1] ^= b;
-        V[2] ^= c;
-        V[3] ^= d;
-        V[4] ^= e;
-        V[5] ^= f;
-        V[6] ^= g;
-        V[7] ^= h;
     }
 
     // write to results in big endian
@@ -143,3 +143,74 @@ pub fn my_hash_impl(input: &[u8]) -> Bytes {
 }
 
 pub const HASH: Hash = my_hash_impl;
+
+// longest message padded into a single block
+pub const MAX_SINGLE_BLOCK_LEN: usize = 55;
+
+// compression state shared by single-block messages starting with a fixed prefix:
+// rounds using only words of the prefix (round j uses W_j and W_{j + 4})
+// are done once, and the rest for each message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixState {
+    prefix_len: usize,
+    // rounds already done
+    rounds: usize,
+    state: [u32; 8],
+}
+
+impl PrefixState {
+    pub fn new(prefix: &[u8]) -> Self {
+        let num_word = prefix.len().min(MAX_SINGLE_BLOCK_LEN) / 4;
+        let rounds = num_word.saturating_sub(4);
+        let mut w = [0u32; 16];
+        for i in 0..num_word {
+            w[i] = (&prefix[4 * i..4 * i + 4]).read_u32::<BigEndian>().unwrap();
+        }
+        let mut state = IV;
+        for i in 0..rounds {
+            round(i, &mut state, w[i], w[i] ^ w[i + 4]);
+        }
+        PrefixState {
+            prefix_len: prefix.len(),
+            rounds,
+            state,
+        }
+    }
+
+    pub fn prefix_len(&self) -> usize {
+        self.prefix_len
+    }
+
+    // hash of a message starting with the prefix, longer messages are hashed from scratch
+    pub fn hash(&self, message: &[u8], output: &mut [u8]) {
+        debug_assert!(message.len() >= self.prefix_len);
+        let len = message.len();
+        if len > MAX_SINGLE_BLOCK_LEN {
+            return my_hash_impl_inplace(message, len, output);
+        }
+
+        // padding: 80 00 00 00 ... [64-bit length]
+        let mut block = [0u8; 64];
+        block[..len].copy_from_slice(message);
+        block[len] = 0x80;
+        (&mut block[56..])
+            .write_u64::<BigEndian>(len as u64 * 8)
+            .unwrap();
+
+        let mut w = [0u32; 68];
+        for i in 0..16 {
+            w[i] = (&block[4 * i..4 * i + 4]).read_u32::<BigEndian>().unwrap();
+        }
+        expand(&mut w);
+
+        let mut state = self.state;
+        for i in self.rounds..64 {
+            round(i, &mut state, w[i], w[i] ^ w[i + 4]);
+        }
+        for i in 0..8 {
+            (&mut output[4 * i..4 * (i + 1)])
+                .write_u32::<BigEndian>(IV[i] ^ state[i])
+                .unwrap();
+        }
+    }
+}
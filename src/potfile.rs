@@ -0,0 +1,89 @@
+// persistent store of cracked hashes, one "hash:plain text" per line
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct Potfile {
+    pub path: PathBuf,
+    pub entries: HashMap<String, Vec<Vec<u8>>>,
+}
+
+// plain texts that are not printable UTF-8 (which may break the line format) are
+// written as $HEX[...], also used to show plain texts in output
+pub fn encode_plain(plain: &[u8]) -> String {
+    match std::str::from_utf8(plain) {
+        Ok(text) if !text.contains(char::is_control) && !text.starts_with("$HEX[") => {
+            text.to_owned()
+        }
+        _ => format!("$HEX[{}]", hex::encode(plain)),
+    }
+}
+
+pub fn decode_plain(text: &str) -> Option<Vec<u8>> {
+    match text.strip_prefix("$HEX[").and_then(|t| t.strip_suffix(']')) {
+        Some(h) => hex::decode(h).ok(),
+        None => Some(text.as_bytes().to_vec()),
+    }
+}
+
+impl Potfile {
+    // a missing file is treated as an empty potfile
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut potfile = Potfile {
+            path: path.as_ref().to_owned(),
+            entries: HashMap::new(),
+        };
+        if !potfile.path.exists() {
+            return Ok(potfile);
+        }
+        let reader = BufReader::new(std::fs::File::open(&potfile.path)?);
+        for line in reader.lines() {
+            let line = line?;
+            // hash has fixed length, so the plain text may contain ':'
+            let mut fields = line.splitn(2, ':');
+            let (hash_str, plain) = match (fields.next(), fields.next()) {
+                (Some(h), Some(p)) if h.len() == 64 => (h.to_ascii_lowercase(), p),
+                _ => continue,
+            };
+            if let Some(plain) = decode_plain(plain) {
+                potfile.insert(hash_str, plain);
+            }
+        }
+        Ok(potfile)
+    }
+
+    fn insert(&mut self, hash_str: String, plain: Vec<u8>) -> bool {
+        let plains = self.entries.entry(hash_str).or_default();
+        if plains.contains(&plain) {
+            false
+        } else {
+            plains.push(plain);
+            true
+        }
+    }
+
+    pub fn get(&self, hash_str: &str) -> Option<&Vec<Vec<u8>>> {
+        self.entries.get(hash_str)
+    }
+
+    // record plain texts of a hash, only new ones are written to the file
+    pub fn append(&mut self, hash_str: &str, plains: &[Vec<u8>]) -> std::io::Result<()> {
+        let mut lines = String::new();
+        for plain in plains {
+            if self.insert(hash_str.to_owned(), plain.clone()) {
+                lines.push_str(&format!("{}:{}\n", hash_str, encode_plain(plain)));
+            }
+        }
+        if !lines.is_empty() {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?
+                .write_all(lines.as_bytes())?;
+        }
+        Ok(())
+    }
+}
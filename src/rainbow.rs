@@ -1,70 +1,570 @@
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use log::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use crate::my_sm3_impl::my_hash_impl_inplace;
+use crate::my_sm3_impl::PrefixState;
+use crate::wordlist::{Rule, Wordlist};
 use crate::*;
 
+// integer type of indices stored in tables
+pub trait IndexInt:
+    Copy
+    + fmt::Debug
+    + fmt::LowerHex
+    + Ord
+    + Hash
+    + Send
+    + Sync
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + 'static
+{
+    // width in bits, recorded in table header
+    const WIDTH: u64;
+    // caller guarantees that v fits
+    fn from_u128(v: u128) -> Self;
+    fn to_u128(self) -> u128;
+    fn as_usize(self) -> usize;
+    // reinterpret leading bytes of hash in little endian
+    fn from_hash_bytes(hash: &[u8]) -> Self;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn gen_below<R: Rng>(rng: &mut R, total: Self) -> Self;
+}
+
+macro_rules! impl_index_int {
+    ($t:ty, $read:ident) => {
+        impl IndexInt for $t {
+            const WIDTH: u64 = <$t>::BITS as u64;
+
+            #[inline]
+            fn from_u128(v: u128) -> Self {
+                v as $t
+            }
+
+            #[inline]
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+
+            #[inline]
+            fn as_usize(self) -> usize {
+                self as usize
+            }
+
+            #[inline]
+            fn from_hash_bytes(mut hash: &[u8]) -> Self {
+                hash.$read::<LittleEndian>().unwrap()
+            }
+
+            #[inline]
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+
+            fn gen_below<R: Rng>(rng: &mut R, total: Self) -> Self {
+                rng.gen_range(0..total)
+            }
+        }
+    };
+}
+
+impl_index_int!(u64, read_u64);
+impl_index_int!(u128, read_u128);
+
+// choose index width for a plain text space
+pub fn index_width_for(space_size: u128) -> u64 {
+    if space_size <= u64::MAX as u128 {
+        u64::WIDTH
+    } else {
+        u128::WIDTH
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub struct RainbowIndex(pub u64);
+pub struct RainbowIndex<I = u64>(pub I);
+
+unsafe impl<I: IndexInt> Send for RainbowIndex<I> {}
+unsafe impl<I: IndexInt> Sync for RainbowIndex<I> {}
+
+// charset whose elements are byte strings (single bytes, UTF-8 code points or tokens)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Charset {
+    pub elements: Vec<Vec<u8>>,
+}
 
-unsafe impl Send for RainbowIndex {}
-unsafe impl Sync for RainbowIndex {}
+impl Charset {
+    // one element for each byte
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Charset {
+            elements: bytes.iter().map(|&b| vec![b]).collect(),
+        }
+    }
+
+    // one element for each UTF-8 code point
+    pub fn from_chars(s: &str) -> Self {
+        Charset {
+            elements: s.chars().map(|c| c.to_string().into_bytes()).collect(),
+        }
+    }
+
+    // one element for each token
+    pub fn from_tokens<S: AsRef<str>>(tokens: &[S]) -> Self {
+        Charset {
+            elements: tokens
+                .iter()
+                .map(|t| t.as_ref().as_bytes().to_vec())
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn min_elem_len(&self) -> usize {
+        self.elements.iter().map(|e| e.len()).min().unwrap_or(0)
+    }
+
+    pub fn max_elem_len(&self) -> usize {
+        self.elements.iter().map(|e| e.len()).max().unwrap_or(0)
+    }
+
+    // serialize as elements each prefixed by its length
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for e in &self.elements {
+            buf.write_u32::<LittleEndian>(e.len() as u32).unwrap();
+            buf.extend_from_slice(e);
+        }
+        buf
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, String> {
+        let mut elements = Vec::new();
+        while !bytes.is_empty() {
+            let len = bytes
+                .read_u32::<LittleEndian>()
+                .map_err(|_| "Charset truncated".to_owned())? as usize;
+            if len == 0 || bytes.len() < len {
+                return Err("Invalid charset element".to_owned());
+            }
+            elements.push(bytes[..len].to_vec());
+            bytes = &bytes[len..];
+        }
+        if elements.is_empty() {
+            return Err("Charset is empty".to_owned());
+        }
+        Ok(Charset { elements })
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all_single = self.elements.iter().all(|e| e.len() == 1);
+        for (i, e) in self.elements.iter().enumerate() {
+            if i > 0 && !all_single {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", String::from_utf8_lossy(e))?;
+        }
+        Ok(())
+    }
+}
+
+// part of plain text space
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Segment {
+    // fixed number of elements with one charset for each position
+    Mask {
+        positions: Vec<Charset>,
+    },
+    // each word mangled by each rule
+    Wordlist {
+        wordlist: Arc<Wordlist>,
+        rules: Vec<Rule>,
+    },
+}
+
+const SEGMENT_KIND_MASK: u32 = 0;
+const SEGMENT_KIND_WORDLIST: u32 = 1;
+
+impl Segment {
+    // number of plain texts in segment, None if it overflows
+    pub fn size(&self) -> Option<u128> {
+        match self {
+            Segment::Mask { positions } => positions
+                .iter()
+                .try_fold(1u128, |acc, p| acc.checked_mul(p.len() as u128)),
+            Segment::Wordlist { wordlist, rules } => rules
+                .iter()
+                .try_fold(0u128, |acc, r| acc.checked_add(r.variants()?))?
+                .checked_mul(wordlist.words.len() as u128),
+        }
+    }
+
+    pub fn min_len(&self) -> usize {
+        match self {
+            Segment::Mask { positions } => positions.iter().map(|p| p.min_elem_len()).sum(),
+            Segment::Wordlist { wordlist, rules } => {
+                wordlist.words.iter().map(|w| w.len()).min().unwrap_or(0)
+                    + rules.iter().map(|r| r.max_extra_len()).min().unwrap_or(0)
+            }
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        match self {
+            Segment::Mask { positions } => positions.iter().map(|p| p.max_elem_len()).sum(),
+            Segment::Wordlist { wordlist, rules } => {
+                wordlist.words.iter().map(|w| w.len()).max().unwrap_or(0)
+                    + rules.iter().map(|r| r.max_extra_len()).max().unwrap_or(0)
+            }
+        }
+    }
+
+    // bytes shared by all plain texts of segment, from positions with a single element
+    pub fn fixed_prefix(&self) -> Vec<u8> {
+        match self {
+            Segment::Mask { positions } => positions
+                .iter()
+                .take_while(|p| p.len() == 1)
+                .flat_map(|p| p.elements[0].iter().cloned())
+                .collect(),
+            Segment::Wordlist { .. } => Vec::new(),
+        }
+    }
+
+    // convert index inside this segment to plain text
+    fn to_plaintext<I: IndexInt>(&self, mut index_x: I, plaintext: &mut [u8]) -> usize {
+        match self {
+            Segment::Mask { positions } => {
+                // fill each position
+                let mut len = 0;
+                for charset in positions {
+                    let charset_len = I::from_u128(charset.len() as u128);
+                    let element = &charset.elements[(index_x % charset_len).as_usize()];
+                    plaintext[len..len + element.len()].copy_from_slice(element);
+                    len += element.len();
+                    index_x = index_x / charset_len;
+                }
+                len
+            }
+            Segment::Wordlist { wordlist, rules } => {
+                // rules are laid out one after another, each covering all words;
+                // indices past the segment wrap around as they do for masks
+                let num_word = wordlist.words.len() as u128;
+                let index = index_x.to_u128();
+                let mut index_x = index;
+                for rule in rules {
+                    // variants are checked when the space is built
+                    let rule_size = num_word * rule.variants().unwrap_or_default();
+                    if index_x < rule_size {
+                        let word = &wordlist.words[(index_x % num_word) as usize];
+                        return rule.apply(word, index_x / num_word, plaintext);
+                    }
+                    index_x -= rule_size;
+                }
+                // what is left is beyond the size of the segment
+                self.to_plaintext(I::from_u128(index % (index - index_x)), plaintext)
+            }
+        }
+    }
+}
+
+// space of all plain texts that chains may contain, as an ordered union of segments
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlaintextSpace {
+    pub segments: Vec<Segment>,
+    // cumulative count of plain texts before each segment (and the total at last)
+    pub offsets: Vec<u128>,
+}
+
+impl PlaintextSpace {
+    pub fn from_segments(segments: Vec<Segment>) -> Result<Self, String> {
+        if segments.is_empty() {
+            return Err("Plain text space has no segments".to_owned());
+        }
+        let mut offsets = Vec::with_capacity(segments.len() + 1);
+        // calculate key space (cumulative)
+        offsets.push(0);
+        for (i, s) in segments.iter().enumerate() {
+            let prefix_sum: u128 = *offsets.last().unwrap();
+            let sum = s
+                .size()
+                .and_then(|size| prefix_sum.checked_add(size))
+                .ok_or_else(|| {
+                    format!("Plain text space too large: exceeds 2^128 at segment {}", i)
+                })?;
+            // plain texts are looked up by index modulo segment size
+            if sum == prefix_sum {
+                return Err(format!("Segment {} of plain text space is empty", i));
+            }
+            offsets.push(sum);
+        }
+        Ok(PlaintextSpace { segments, offsets })
+    }
+
+    // one charset for all positions over a range of element counts, one segment for each count
+    pub fn from_charset(charset: &Charset, len_range: &Range<usize>) -> Result<Self, String> {
+        PlaintextSpace::from_segments(
+            len_range
+                .clone()
+                .map(|l| Segment::Mask {
+                    positions: vec![charset.clone(); l],
+                })
+                .collect(),
+        )
+    }
+
+    // union of masks, one segment for each mask
+    pub fn from_masks(
+        masks: &[String],
+        custom_charsets: &[Option<String>],
+    ) -> Result<Self, String> {
+        let segments = masks
+            .iter()
+            .map(|m| {
+                Ok(Segment::Mask {
+                    positions: crate::mask::parse_mask(m, custom_charsets)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if segments.is_empty() {
+            return Err("No mask specified".to_owned());
+        }
+        PlaintextSpace::from_segments(segments)
+    }
+
+    // hashing state of the fixed prefix of each segment
+    pub fn prefix_states(&self) -> Vec<PrefixState> {
+        self.segments
+            .iter()
+            .map(|s| PrefixState::new(&s.fixed_prefix()))
+            .collect()
+    }
 
-impl RainbowIndex {
+    // total number of plain texts
+    pub fn size(&self) -> u128 {
+        *self.offsets.last().unwrap()
+    }
+
+    // min length of plain text
+    pub fn min_len(&self) -> usize {
+        self.segments.iter().map(|s| s.min_len()).min().unwrap()
+    }
+
+    // max length of plain text, used to allocate buffers
+    pub fn max_len(&self) -> usize {
+        self.segments.iter().map(|s| s.max_len()).max().unwrap()
+    }
+
+    // serialize to the descriptor stored after charset in table header
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(self.segments.len() as u32)
+            .unwrap();
+        for s in &self.segments {
+            match s {
+                Segment::Mask { positions } => {
+                    buf.write_u32::<LittleEndian>(SEGMENT_KIND_MASK).unwrap();
+                    buf.write_u32::<LittleEndian>(positions.len() as u32)
+                        .unwrap();
+                    for p in positions {
+                        let charset = p.encode();
+                        buf.write_u32::<LittleEndian>(charset.len() as u32).unwrap();
+                        buf.extend_from_slice(&charset);
+                    }
+                }
+                Segment::Wordlist { wordlist, rules } => {
+                    buf.write_u32::<LittleEndian>(SEGMENT_KIND_WORDLIST)
+                        .unwrap();
+                    buf.extend_from_slice(&wordlist.digest);
+                    buf.write_u64::<LittleEndian>(wordlist.words.len() as u64)
+                        .unwrap();
+                    buf.write_u32::<LittleEndian>(rules.len() as u32).unwrap();
+                    for r in rules {
+                        buf.write_u32::<LittleEndian>(r.text.len() as u32).unwrap();
+                        buf.extend_from_slice(r.text.as_bytes());
+                    }
+                }
+            }
+        }
+        buf
+    }
+
+    // deserialize from descriptor, wordlists referenced by digest must be provided
+    pub fn decode(mut desc: &[u8], wordlists: &[Arc<Wordlist>]) -> Result<Self, String> {
+        fn read_bytes<'a>(desc: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+            if desc.len() < len {
+                return Err("Plain text space descriptor truncated".to_owned());
+            }
+            let (bytes, rest) = desc.split_at(len);
+            *desc = rest;
+            Ok(bytes)
+        }
+        let truncated = |_| "Plain text space descriptor truncated".to_owned();
+
+        let num_segment = desc.read_u32::<LittleEndian>().map_err(truncated)?;
+        let mut segments = Vec::new();
+        for _ in 0..num_segment {
+            let kind = desc.read_u32::<LittleEndian>().map_err(truncated)?;
+            let segment = match kind {
+                SEGMENT_KIND_MASK => {
+                    let num_position = desc.read_u32::<LittleEndian>().map_err(truncated)?;
+                    let mut positions = Vec::new();
+                    for _ in 0..num_position {
+                        let len = desc.read_u32::<LittleEndian>().map_err(truncated)? as usize;
+                        positions.push(Charset::decode(read_bytes(&mut desc, len)?)?);
+                    }
+                    if positions.is_empty() {
+                        return Err("Empty mask".to_owned());
+                    }
+                    Segment::Mask { positions }
+                }
+                SEGMENT_KIND_WORDLIST => {
+                    let digest = read_bytes(&mut desc, 32)?;
+                    let num_word = desc.read_u64::<LittleEndian>().map_err(truncated)?;
+                    if num_word == 0 {
+                        return Err("Empty wordlist".to_owned());
+                    }
+                    let wordlist = wordlists
+                        .iter()
+                        .find(|w| w.digest == digest && w.words.len() as u64 == num_word)
+                        .ok_or_else(|| {
+                            format!("Wordlist with digest {} not provided", hex::encode(digest))
+                        })?;
+                    let num_rule = desc.read_u32::<LittleEndian>().map_err(truncated)?;
+                    let mut rules = Vec::new();
+                    for _ in 0..num_rule {
+                        let len = desc.read_u32::<LittleEndian>().map_err(truncated)? as usize;
+                        let text = String::from_utf8_lossy(read_bytes(&mut desc, len)?);
+                        rules.push(Rule::parse(&text)?);
+                    }
+                    if rules.is_empty() {
+                        return Err("No rules for wordlist".to_owned());
+                    }
+                    Segment::Wordlist {
+                        wordlist: wordlist.clone(),
+                        rules,
+                    }
+                }
+                _ => return Err(format!("Unknown segment kind {}", kind)),
+            };
+            segments.push(segment);
+        }
+        if segments.is_empty() || !desc.is_empty() {
+            return Err("Invalid plain text space descriptor".to_owned());
+        }
+        PlaintextSpace::from_segments(segments)
+    }
+}
+
+// hash function applied to plain texts of chains; the built-in implementation
+// reuses the rounds of the fixed prefix of each segment, computed once per space
+#[derive(Clone)]
+pub enum ChainHasher {
+    Prefix(Vec<PrefixState>),
+    Plain(crate::Hash),
+}
+
+impl ChainHasher {
+    // built-in implementation for plain texts of space
+    pub fn new(space: &PlaintextSpace) -> Self {
+        ChainHasher::Prefix(space.prefix_states())
+    }
+
+    #[inline]
+    pub fn hash(&self, segment: usize, plaintext: &[u8], output: &mut [u8; 32]) {
+        match self {
+            ChainHasher::Prefix(states) => states[segment].hash(plaintext, output),
+            ChainHasher::Plain(hasher) => output.copy_from_slice(&hasher(plaintext)),
+        }
+    }
+}
+
+impl fmt::Debug for ChainHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainHasher::Prefix(states) => write!(f, "Prefix({} segments)", states.len()),
+            ChainHasher::Plain(_) => write!(f, "Plain"),
+        }
+    }
+}
+
+impl<I: IndexInt> RainbowIndex<I> {
     // convert index to plain text
-    pub fn to_plaintext(
+    pub fn to_plaintext(&self, space: &PlaintextSpace, plaintext: &mut [u8]) -> usize {
+        self.to_plaintext_in_segment(space, plaintext).1
+    }
+
+    // convert index to plain text, return (segment, length)
+    pub fn to_plaintext_in_segment(
         &self,
-        charset: &[u8],
-        plaintext_len_range: &Range<usize>,
-        plaintext_lens: &[u64],
+        space: &PlaintextSpace,
         plaintext: &mut [u8],
-    ) -> usize {
-        let index = self.0;
+    ) -> (usize, usize) {
+        let index = self.0.to_u128();
         let mut index_x = index;
-        let mut plaintext_len = 0;
-
-        // calculate length
-        for l in plaintext_len_range.clone().rev() {
-            let len_index = l - 1;
-            if self.0 >= plaintext_lens[len_index] {
-                plaintext_len = l;
-                index_x = index - plaintext_lens[len_index];
+        let mut segment = 0;
+
+        // find segment
+        for i in (0..space.segments.len()).rev() {
+            if index >= space.offsets[i] {
+                segment = i;
+                index_x = index - space.offsets[i];
                 break;
             }
         }
 
-        // fill prefix
-        for l in 0..plaintext_len {
-            let charset_len = charset.len() as u64;
-            plaintext[l as usize] = charset[(index_x % charset_len) as usize];
-            index_x /= charset_len;
-        }
-
-        // return length to plain text
-        plaintext_len
+        let len = space.segments[segment].to_plaintext(I::from_u128(index_x), plaintext);
+        (segment, len)
     }
 
     // reduction functions (from hash to index according to pos)
     pub fn from_hash(
         hash: &[u8],
         reduction_offset: u64,
-        plaintext_space_total: u64,
+        plaintext_space_total: I,
         pos: u32,
     ) -> Self {
-        // reinterpret hash[0..8] as u64
-        let ret = (&hash[0..8]).read_u64::<LittleEndian>().unwrap();
-        RainbowIndex((ret + reduction_offset + pos as u64) % plaintext_space_total)
+        // reinterpret leading bytes of hash as index
+        let ret = I::from_hash_bytes(hash);
+        let shift = I::from_u128(reduction_offset as u128 + pos as u128);
+        RainbowIndex(ret.wrapping_add(shift) % plaintext_space_total)
+    }
+
+    // random index derived only from (seed, table index, round, chain number)
+    // so that heads do not depend on thread scheduling
+    pub fn from_seed(
+        seed: u64,
+        table_index: u64,
+        round: u64,
+        chain: u64,
+        plaintext_space_total: I,
+    ) -> Self {
+        let mut key = [0u8; 32];
+        key[0..8].copy_from_slice(&seed.to_le_bytes());
+        key[8..16].copy_from_slice(&table_index.to_le_bytes());
+        key[16..24].copy_from_slice(&round.to_le_bytes());
+        key[24..32].copy_from_slice(&chain.to_le_bytes());
+        let mut rng = ChaCha8Rng::from_seed(key);
+        RainbowIndex(I::gen_below(&mut rng, plaintext_space_total))
     }
 
     // traverse the chain from certain position, return the tail index
     pub fn traverse_chain<F>(
-        head: RainbowIndex,
-        charset: &[u8],
-        plaintext_len_range: &Range<usize>,
-        plaintext_lens: &[u64],
+        head: RainbowIndex<I>,
+        space: &PlaintextSpace,
+        hasher: &ChainHasher,
         start_pos: usize,
         length: usize,
         reduction_offset: u64,
@@ -81,21 +581,16 @@ impl RainbowIndex {
 
         // buffer for plain text
         let mut plaintext: Vec<u8> = Vec::new();
-        let max_len = (plaintext_len_range.end - 1) as usize;
+        let max_len = space.max_len();
         plaintext.resize(max_len, 0x3f); // fill with '?'
 
         // buffer for output
         let mut hash = [0u8; 32];
-        let total_space = *plaintext_lens.last().unwrap();
+        let total_space = I::from_u128(space.size());
 
         for pos in start_pos..start_pos + length {
-            let len = index.to_plaintext(
-                &charset,
-                &plaintext_len_range,
-                &plaintext_lens,
-                &mut plaintext,
-            );
-            my_hash_impl_inplace(&plaintext, len as usize, &mut hash);
+            let (segment, len) = index.to_plaintext_in_segment(space, &mut plaintext);
+            hasher.hash(segment, &plaintext[..len], &mut hash);
             index = RainbowIndex::from_hash(&hash, reduction_offset, total_space, pos as u32);
             // log each step
             if log_enabled!(log::Level::Debug) {
@@ -124,57 +619,228 @@ impl RainbowIndex {
     }
 }
 
+// plain texts of consecutive indices, each made by incrementing the previous one
+// like an odometer instead of dividing the index for every position
+pub struct PlaintextIter<'a> {
+    space: &'a PlaintextSpace,
+    // index of the current plain text
+    index: u128,
+    end: u128,
+    segment: usize,
+    // element of each position for masks, (rule, word) for wordlists
+    digits: Vec<usize>,
+    // variant of the rule for wordlists
+    variant: u128,
+    // byte offset of each position for masks
+    positions: Vec<usize>,
+    plaintext: Vec<u8>,
+    len: usize,
+    started: bool,
+}
+
+impl<'a> PlaintextIter<'a> {
+    pub fn new(space: &'a PlaintextSpace, range: Range<u128>) -> Self {
+        let mut iter = PlaintextIter {
+            space,
+            index: range.start,
+            end: range.end.min(space.size()),
+            segment: 0,
+            digits: Vec::new(),
+            variant: 0,
+            positions: Vec::new(),
+            plaintext: vec![0u8; space.max_len()],
+            len: 0,
+            started: false,
+        };
+        if iter.index < iter.end {
+            iter.seek(range.start);
+        }
+        iter
+    }
+
+    // index of the plain text last returned
+    pub fn index(&self) -> u128 {
+        self.index
+    }
+
+    // segment of the plain text last returned
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    // plain text last returned
+    pub fn plaintext(&self) -> &[u8] {
+        &self.plaintext[..self.len]
+    }
+
+    // move to the next plain text, None after the end of range
+    pub fn next_plaintext(&mut self) -> Option<&[u8]> {
+        if self.started {
+            self.index += 1;
+            if self.index >= self.end {
+                self.index = self.end;
+                return None;
+            }
+            if self.index == self.space.offsets[self.segment + 1] {
+                self.seek(self.index);
+            } else {
+                self.increment();
+            }
+        } else if self.index >= self.end {
+            return None;
+        }
+        self.started = true;
+        Some(&self.plaintext[..self.len])
+    }
+
+    // decompose index once, as to_plaintext does
+    fn seek(&mut self, index: u128) {
+        let offsets = &self.space.offsets;
+        self.segment = (0..self.space.segments.len())
+            .rev()
+            .find(|&i| index >= offsets[i])
+            .unwrap();
+        let mut index_x = index - offsets[self.segment];
+        self.digits.clear();
+        match &self.space.segments[self.segment] {
+            Segment::Mask { positions } => {
+                for charset in positions {
+                    let charset_len = charset.len() as u128;
+                    self.digits.push((index_x % charset_len) as usize);
+                    index_x /= charset_len;
+                }
+            }
+            Segment::Wordlist { wordlist, rules } => {
+                let num_word = wordlist.words.len() as u128;
+                let mut rule = 0;
+                while index_x >= num_word * rules[rule].variants().unwrap_or_default() {
+                    index_x -= num_word * rules[rule].variants().unwrap_or_default();
+                    rule += 1;
+                }
+                self.digits.push(rule);
+                self.digits.push((index_x % num_word) as usize);
+                self.variant = index_x / num_word;
+            }
+        }
+        self.render();
+    }
+
+    // write the whole plain text from digits
+    fn render(&mut self) {
+        match &self.space.segments[self.segment] {
+            Segment::Mask { positions } => {
+                self.positions.clear();
+                let mut len = 0;
+                for (charset, &digit) in positions.iter().zip(&self.digits) {
+                    let element = &charset.elements[digit];
+                    self.positions.push(len);
+                    self.plaintext[len..len + element.len()].copy_from_slice(element);
+                    len += element.len();
+                }
+                self.len = len;
+            }
+            Segment::Wordlist { wordlist, rules } => {
+                let word = &wordlist.words[self.digits[1]];
+                self.len = rules[self.digits[0]].apply(word, self.variant, &mut self.plaintext);
+            }
+        }
+    }
+
+    // add one inside the current segment
+    fn increment(&mut self) {
+        match &self.space.segments[self.segment] {
+            Segment::Mask { positions } => {
+                // carry from the first position, rewriting only changed positions
+                // unless an element of another length moves those after it
+                let mut resize = false;
+                for (i, charset) in positions.iter().enumerate() {
+                    let old_len = charset.elements[self.digits[i]].len();
+                    self.digits[i] += 1;
+                    if self.digits[i] == charset.len() {
+                        self.digits[i] = 0;
+                    }
+                    let element = &charset.elements[self.digits[i]];
+                    if element.len() != old_len {
+                        resize = true;
+                    } else if !resize {
+                        let pos = self.positions[i];
+                        self.plaintext[pos..pos + element.len()].copy_from_slice(element);
+                    }
+                    if self.digits[i] != 0 {
+                        break;
+                    }
+                }
+                if resize {
+                    self.render();
+                }
+            }
+            Segment::Wordlist { wordlist, rules } => {
+                self.digits[1] += 1;
+                if self.digits[1] == wordlist.words.len() {
+                    self.digits[1] = 0;
+                    self.variant += 1;
+                    if Some(self.variant) == rules[self.digits[0]].variants() {
+                        self.variant = 0;
+                        self.digits[0] += 1;
+                    }
+                }
+                self.render();
+            }
+        }
+    }
+}
+
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Eq)]
+#[derive(Copy, Clone, Debug)]
 // a chain in the rainbow table
-pub struct RainbowChain {
-    pub head: RainbowIndex,
-    pub tail: RainbowIndex,
+pub struct RainbowChain<I = u64> {
+    pub head: RainbowIndex<I>,
+    pub tail: RainbowIndex<I>,
 }
 
-impl PartialEq for RainbowChain {
+impl<I: IndexInt> PartialEq for RainbowChain<I> {
     fn eq(&self, other: &Self) -> bool {
         self.tail == other.tail
     }
 }
 
-impl Ord for RainbowChain {
+impl<I: IndexInt> Eq for RainbowChain<I> {}
+
+impl<I: IndexInt> Ord for RainbowChain<I> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.tail.cmp(&other.tail)
     }
 }
 
-impl PartialOrd for RainbowChain {
+impl<I: IndexInt> PartialOrd for RainbowChain<I> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Hash for RainbowChain {
+impl<I: IndexInt> Hash for RainbowChain<I> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.head.hash(state)
     }
 }
 
-unsafe impl Send for RainbowChain {}
-unsafe impl Sync for RainbowChain {}
+unsafe impl<I: IndexInt> Send for RainbowChain<I> {}
+unsafe impl<I: IndexInt> Sync for RainbowChain<I> {}
 
-impl RainbowChain {
+impl<I: IndexInt> RainbowChain<I> {
     // generate a chain from index as head
     pub fn from_index(
-        head: RainbowIndex,
-        charset: &[u8],
-        plaintext_len_range: &Range<usize>,
-        plaintext_lens: &[u64],
+        head: RainbowIndex<I>,
+        space: &PlaintextSpace,
+        hasher: &ChainHasher,
         start_pos: usize,
         length: usize,
         reduction_offset: u64,
     ) -> Self {
         let tail = RainbowIndex::traverse_chain(
             head,
-            charset,
-            plaintext_len_range,
-            plaintext_lens,
+            space,
+            hasher,
             start_pos,
             length,
             reduction_offset,
@@ -187,9 +853,8 @@ impl RainbowChain {
     pub fn find_match(
         &self,
         target_hash: &[u8],
-        charset: &[u8],
-        plaintext_len_range: &Range<usize>,
-        plaintext_lens: &[u64],
+        space: &PlaintextSpace,
+        hasher: &ChainHasher,
         length: usize,
         reduction_offset: u64,
     ) -> Option<Vec<u8>> {
@@ -202,9 +867,8 @@ impl RainbowChain {
         // check hash in each loop
         RainbowIndex::traverse_chain(
             self.head,
-            charset,
-            plaintext_len_range,
-            plaintext_lens,
+            space,
+            hasher,
             0,
             length,
             reduction_offset,
@@ -231,35 +895,83 @@ impl RainbowChain {
 // header of rainbow table
 pub struct RainbowTableHeader {
     pub magic: u64,
+    pub version: u64,
     pub num_chain: u64,
     pub chain_len: u64,
     pub table_index: u64,
     pub min_length: u32,
     pub max_length: u32,
     pub charset_length: u64,
+    pub seed: u64,
+    pub space_length: u64,
+    pub index_width: u64,
 }
 
 impl PartialEq for RainbowTableHeader {
     fn eq(&self, other: &Self) -> bool {
         self.magic == other.magic
+            && self.version == other.version
             && self.num_chain == other.num_chain
             && self.chain_len == other.chain_len
             && self.min_length == other.min_length
             && self.max_length == other.max_length
             && self.charset_length == other.charset_length
+            && self.space_length == other.space_length
+            && self.index_width == other.index_width
     }
 }
 
 impl RainbowTableHeader {
+    // tables written before versioning have num_chain where version is now
+    pub fn is_legacy(&self) -> bool {
+        self.magic == RAINBOW_TABLE_HEADER_MAGIC && self.version > RAINBOW_TABLE_VERSION
+    }
+
     pub fn is_valid(&self) -> bool {
         self.magic == RAINBOW_TABLE_HEADER_MAGIC
+            && self.version == RAINBOW_TABLE_VERSION
             && self.min_length > 0
-            && self.max_length > 0
-            && self.charset_length > 0
+            && self.min_length <= self.max_length
+            && (self.charset_length > 0 || self.space_length > 0)
+            && (self.index_width == u64::WIDTH || self.index_width == u128::WIDTH)
             && self.num_chain > 0
             && self.chain_len > 0
     }
+
+    // offset of rainbow chains from the beginning of file, aligned to the size of index
+    pub fn data_offset(&self) -> usize {
+        let len = std::mem::size_of::<RainbowTableHeader>()
+            + padded_len(self.charset_length as usize)
+            + padded_len(self.space_length as usize);
+        let align = (self.index_width / 8) as usize;
+        len.div_ceil(align) * align
+    }
+
+    // length of the whole table without metadata, None on overflow;
+    // lengths come from files, so all arithmetic is checked
+    pub fn table_len(&self) -> Option<u64> {
+        let padded = |len: u64| len.checked_add(7).map(|l| l & !7);
+        let align = self.index_width / 8;
+        if align == 0 {
+            return None;
+        }
+        let len = (std::mem::size_of::<RainbowTableHeader>() as u64)
+            .checked_add(padded(self.charset_length)?)?
+            .checked_add(padded(self.space_length)?)?
+            .checked_add(align - 1)?
+            / align
+            * align;
+        self.num_chain.checked_mul(2 * align)?.checked_add(len)
+    }
+}
+
+// length of variable-length header fields after zero padding to 8 bytes
+pub fn padded_len(len: usize) -> usize {
+    (len + 7) & !7usize
 }
 
 // "HCRNBWTB" (Harry Chen RaiNBoW TaBle) in little endian
 pub const RAINBOW_TABLE_HEADER_MAGIC: u64 = 0x425457424e524348;
+
+// bumped whenever the layout of the header changes
+pub const RAINBOW_TABLE_VERSION: u64 = 7;
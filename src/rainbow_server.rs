@@ -0,0 +1,136 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Clap;
+use log::*;
+use sm3::error::Error;
+use sm3::server::{self, Client, ServerOptions};
+use sm3::table::TableSet;
+use sm3::wordlist::Wordlist;
+
+#[derive(Clap, Debug)]
+#[clap(
+    name = "rainbow_server",
+    version = "0.1",
+    author = "Shengqi Chen <i@harrychen.xyz>",
+    about = "Serve lookups in rainbow tables of SM3 hash algorithm over TCP"
+)]
+pub struct ServerCommand {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clap, Debug)]
+pub enum Command {
+    /// Load tables once and serve lookup requests
+    Serve(ServeOptions),
+    /// Send requests to a running server
+    Client(ClientOptions),
+}
+
+#[derive(Clap, Debug)]
+pub struct ServeOptions {
+    #[clap(short = 't', long, required = true)]
+    pub table_files: Vec<String>,
+    #[clap(short = 'w', long)]
+    /// Wordlists used by tables (matched by content digest)
+    pub wordlist: Vec<String>,
+    #[clap(short = 'l', long, default_value = "127.0.0.1:7878")]
+    /// Address to listen on
+    pub listen: String,
+    #[clap(short = 'j', long, default_value = "1")]
+    /// Number of jobs looked up at the same time
+    pub max_jobs: usize,
+    #[clap(short = 'q', long, default_value = "16")]
+    /// Number of jobs queued or running before rejecting new ones
+    pub max_pending: usize,
+    #[clap(long, default_value = "256")]
+    /// Number of finished jobs kept until their results are fetched
+    pub max_done: usize,
+    #[clap(long)]
+    /// Stop looking up a hash after this many seconds
+    pub hash_timeout: Option<f64>,
+}
+
+#[derive(Clap, Debug)]
+pub struct ClientOptions {
+    #[clap(short = 's', long, default_value = "127.0.0.1:7878")]
+    /// Address of the server
+    pub server: String,
+    #[clap(subcommand)]
+    pub request: Request,
+}
+
+#[derive(Clap, Debug)]
+pub enum Request {
+    /// Submit hashes and print the job id
+    Submit { hashes: Vec<String> },
+    /// Print the status of a job
+    Status { job: u64 },
+    /// Print the status and results of a job
+    Result { job: u64 },
+    /// Submit hashes and wait for the results
+    Lookup { hashes: Vec<String> },
+}
+
+fn run_serve(opts: &ServeOptions) -> Result<(), Error> {
+    let wordlists = opts
+        .wordlist
+        .iter()
+        .map(|f| Wordlist::load(f).map(Arc::new))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let tables = TableSet::open(&opts.table_files, &wordlists)?;
+    let listener = TcpListener::bind(&opts.listen)?;
+    println!("Listening on {}", listener.local_addr()?);
+    let options = ServerOptions {
+        max_jobs: opts.max_jobs,
+        max_pending: opts.max_pending,
+        max_done: opts.max_done,
+        hash_timeout: opts.hash_timeout.map(Duration::from_secs_f64),
+    };
+    server::serve(listener, tables, options)
+}
+
+fn run_client(opts: &ClientOptions) -> Result<(), Error> {
+    let mut client = Client::connect(&opts.server)?;
+    match &opts.request {
+        Request::Submit { hashes } => println!("{}", client.submit(hashes)?),
+        Request::Status { job } => {
+            println!("{}", serde_json::to_string(&client.status(*job)?).unwrap())
+        }
+        Request::Result { job } => {
+            println!("{}", serde_json::to_string(&client.result(*job)?).unwrap())
+        }
+        Request::Lookup { hashes } => {
+            for report in client.lookup(hashes, Duration::from_millis(200))? {
+                if report.plaintexts.is_empty() {
+                    println!(
+                        "Failed to find plain text for {} ({})",
+                        report.hash, report.status
+                    );
+                } else {
+                    println!(
+                        "Found plain text for {}: {:?}",
+                        report.hash, report.plaintexts
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    env_logger::builder().init();
+    let opts: ServerCommand = ServerCommand::parse();
+    info!("Program options: {:?}", opts);
+    let result = match &opts.command {
+        Command::Serve(serve) => run_serve(serve),
+        Command::Client(client) => run_client(client),
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
@@ -0,0 +1,381 @@
+// serving lookups in a table set over a line-based TCP protocol
+//
+// each request is one line, answered by one JSON object per line:
+//   SUBMIT <hash> [<hash> ...]  -> {"ok":true,"job":1}
+//   STATUS <job>                -> {"ok":true,"job":1,"state":"running","done":2,"total":5}
+//   RESULT <job>                -> as STATUS, with "results" once the job is done,
+//                                  after which the job is forgotten
+// failed requests are answered by {"ok":false,"error":"..."}
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::potfile::encode_plain;
+use crate::table::{Budget, TableSet};
+use crate::target::parse_hash;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+}
+
+// lookup result of a hash in a job
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HashReport {
+    pub hash: String,
+    pub status: String,
+    // as in potfiles, $HEX[...] unless printable UTF-8
+    pub plaintexts: Vec<String>,
+    pub false_alarms: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<JobState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<HashReport>>,
+}
+
+impl Response {
+    fn error(message: String) -> Self {
+        Response {
+            ok: false,
+            error: Some(message),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    // jobs looked up at the same time
+    pub max_jobs: usize,
+    // jobs queued or running, more submissions are rejected
+    pub max_pending: usize,
+    // finished jobs kept until their results are fetched, the oldest are dropped beyond this
+    pub max_done: usize,
+    pub hash_timeout: Option<Duration>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_jobs: 1,
+            max_pending: 16,
+            max_done: 256,
+            hash_timeout: None,
+        }
+    }
+}
+
+struct Job {
+    hashes: Vec<[u8; 32]>,
+    state: JobState,
+    results: Vec<HashReport>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: HashMap<u64, Job>,
+}
+
+impl Jobs {
+    fn status(&self, id: u64, with_results: bool) -> Response {
+        match self.jobs.get(&id) {
+            Some(job) => Response {
+                ok: true,
+                job: Some(id),
+                state: Some(job.state),
+                done: Some(job.results.len()),
+                total: Some(job.hashes.len()),
+                results: if with_results && job.state == JobState::Done {
+                    Some(job.results.clone())
+                } else {
+                    None
+                },
+                ..Default::default()
+            },
+            None => Response::error(format!("No job {}", id)),
+        }
+    }
+
+    // drop the oldest finished jobs beyond max_done
+    fn evict(&mut self, max_done: usize) {
+        let mut done: Vec<u64> = self
+            .jobs
+            .iter()
+            .filter(|(_, j)| j.state == JobState::Done)
+            .map(|(&id, _)| id)
+            .collect();
+        if done.len() > max_done {
+            done.sort_unstable();
+            for id in &done[..done.len() - max_done] {
+                debug!("Dropping results of job {}", id);
+                self.jobs.remove(id);
+            }
+        }
+    }
+}
+
+// look up queued jobs one by one
+fn run_worker(
+    tables: &TableSet,
+    jobs: &Mutex<Jobs>,
+    queue: &Mutex<Receiver<u64>>,
+    options: &ServerOptions,
+) {
+    let progress = ProgressBar::hidden();
+    loop {
+        let id = match queue.lock().unwrap().recv() {
+            Ok(id) => id,
+            Err(_) => return,
+        };
+        let hashes = {
+            let mut jobs = jobs.lock().unwrap();
+            let job = jobs.jobs.get_mut(&id).unwrap();
+            job.state = JobState::Running;
+            job.hashes.clone()
+        };
+        info!("Start looking up {} hashes of job {}", hashes.len(), id);
+        for hash in &hashes {
+            let budget = Budget::new(options.hash_timeout);
+            let mut result = tables.search_all(hash, &budget, &progress);
+            result.dedup();
+            let report = HashReport {
+                hash: hex::encode(hash),
+                status: result.status().to_owned(),
+                plaintexts: result.plains().iter().map(|p| encode_plain(p)).collect(),
+                false_alarms: result.false_alarms,
+            };
+            jobs.lock()
+                .unwrap()
+                .jobs
+                .get_mut(&id)
+                .unwrap()
+                .results
+                .push(report);
+        }
+        let mut jobs = jobs.lock().unwrap();
+        jobs.jobs.get_mut(&id).unwrap().state = JobState::Done;
+        jobs.evict(options.max_done);
+        info!("Finish job {}", id);
+    }
+}
+
+fn handle_request(
+    line: &str,
+    jobs: &Mutex<Jobs>,
+    submit: &dyn Fn(u64) -> bool,
+    options: &ServerOptions,
+) -> Response {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default().to_ascii_uppercase();
+    let args: Vec<&str> = words.collect();
+    let job_id = || match args.as_slice() {
+        [id] => id
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid job id: {}", id)),
+        _ => Err(format!("{} needs a job id", command)),
+    };
+    match command.as_str() {
+        "SUBMIT" => {
+            if args.is_empty() {
+                return Response::error("SUBMIT needs at least one hash".to_owned());
+            }
+            let mut hashes = Vec::new();
+            for a in &args {
+                match parse_hash(a) {
+                    Some(hash) => hashes.push(hash),
+                    None => return Response::error(format!("Invalid hash: {}", a)),
+                }
+            }
+            let mut jobs = jobs.lock().unwrap();
+            let pending = jobs
+                .jobs
+                .values()
+                .filter(|j| j.state != JobState::Done)
+                .count();
+            if pending >= options.max_pending {
+                return Response::error(format!("Too many pending jobs ({})", pending));
+            }
+            jobs.next_id += 1;
+            let id = jobs.next_id;
+            jobs.jobs.insert(
+                id,
+                Job {
+                    hashes,
+                    state: JobState::Queued,
+                    results: Vec::new(),
+                },
+            );
+            // workers only exit if they panicked
+            if !submit(id) {
+                jobs.jobs.remove(&id);
+                return Response::error("No worker is running".to_owned());
+            }
+            Response {
+                ok: true,
+                job: Some(id),
+                ..Default::default()
+            }
+        }
+        "STATUS" => match job_id() {
+            Ok(id) => jobs.lock().unwrap().status(id, false),
+            Err(e) => Response::error(e),
+        },
+        "RESULT" => match job_id() {
+            Ok(id) => {
+                let mut jobs = jobs.lock().unwrap();
+                let response = jobs.status(id, true);
+                // results are fetched only once
+                if response.results.is_some() {
+                    jobs.jobs.remove(&id);
+                }
+                response
+            }
+            Err(e) => Response::error(e),
+        },
+        _ => Response::error(format!("Unknown command: {}", command)),
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    jobs: &Mutex<Jobs>,
+    submit: &dyn Fn(u64) -> bool,
+    options: &ServerOptions,
+) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_request(&line, jobs, submit, options);
+        writeln!(writer, "{}", serde_json::to_string(&response).unwrap())?;
+    }
+    Ok(())
+}
+
+// serve lookups in tables, each connection in its own thread
+pub fn serve(listener: TcpListener, tables: TableSet, options: ServerOptions) -> Result<()> {
+    let tables = Arc::new(tables);
+    let jobs = Arc::new(Mutex::new(Jobs::default()));
+    let (sender, receiver) = channel::<u64>();
+    let queue = Arc::new(Mutex::new(receiver));
+    let options = Arc::new(options);
+
+    for _ in 0..options.max_jobs.max(1) {
+        let (tables, jobs, queue, options) =
+            (tables.clone(), jobs.clone(), queue.clone(), options.clone());
+        std::thread::spawn(move || run_worker(&tables, &jobs, &queue, &options));
+    }
+
+    info!("Serving lookups on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        // a failed connection (or running out of file descriptors for a while) is not fatal
+        let (stream, peer) = match stream.and_then(|s| s.peer_addr().map(|peer| (s, peer))) {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Cannot accept connection: {}", e);
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        };
+        debug!("Accepted connection from {}", peer);
+        let (jobs, options, sender) = (jobs.clone(), options.clone(), sender.clone());
+        std::thread::spawn(move || {
+            let submit = |id: u64| sender.send(id).is_ok();
+            if let Err(e) = handle_connection(stream, &jobs, &submit, &options) {
+                warn!("Connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// client of the lookup server
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let writer = TcpStream::connect(addr)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Client { reader, writer })
+    }
+
+    // send a request line, failed requests are returned as errors
+    pub fn request(&mut self, line: &str) -> Result<Response> {
+        writeln!(self.writer, "{}", line)?;
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "Connection closed by server",
+            )));
+        }
+        let response: Response = serde_json::from_str(&response)
+            .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+        if response.ok {
+            Ok(response)
+        } else {
+            Err(Error::InvalidInput(response.error.unwrap_or_default()))
+        }
+    }
+
+    pub fn submit<S: AsRef<str>>(&mut self, hashes: &[S]) -> Result<u64> {
+        let hashes: Vec<&str> = hashes.iter().map(|h| h.as_ref()).collect();
+        let response = self.request(&format!("SUBMIT {}", hashes.join(" ")))?;
+        Ok(response.job.unwrap_or_default())
+    }
+
+    pub fn status(&mut self, job: u64) -> Result<Response> {
+        self.request(&format!("STATUS {}", job))
+    }
+
+    pub fn result(&mut self, job: u64) -> Result<Response> {
+        self.request(&format!("RESULT {}", job))
+    }
+
+    // submit hashes and poll until they are looked up
+    pub fn lookup<S: AsRef<str>>(
+        &mut self,
+        hashes: &[S],
+        interval: Duration,
+    ) -> Result<Vec<HashReport>> {
+        let job = self.submit(hashes)?;
+        loop {
+            let response = self.result(job)?;
+            if let Some(results) = response.results {
+                return Ok(results);
+            }
+            std::thread::sleep(interval);
+        }
+    }
+}
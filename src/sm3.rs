@@ -47,9 +47,21 @@ impl fmt::Debug for Bytes {
 
 pub type Hash = fn(input: &[u8]) -> Bytes;
 
+pub mod brute;
+pub mod builder;
+pub mod cache;
+pub mod catalog;
+pub mod error;
+pub mod mask;
+pub mod metadata;
 pub mod my_sm3_impl;
 pub mod openssl_sm3_impl;
+pub mod potfile;
 pub mod rainbow;
+pub mod server;
+pub mod table;
+pub mod target;
+pub mod wordlist;
 
 pub const OPENSSL_SM3: Hash = openssl_sm3_impl::HASH;
 pub const MY_SM3: Hash = my_sm3_impl::HASH;
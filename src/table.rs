@@ -0,0 +1,973 @@
+// reading rainbow tables and looking up hashes in them
+
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use indicatif::ProgressBar;
+use log::*;
+use memmap::{Mmap, MmapOptions};
+use rayon::prelude::*;
+use serde::{Serialize, Serializer};
+
+use crate::cache::TailCache;
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+use crate::potfile::encode_plain;
+use crate::rainbow::*;
+use crate::wordlist::Wordlist;
+use crate::MY_SM3;
+
+// plain texts are shown as in potfile
+fn serialize_plain<S: Serializer>(
+    plain: &[u8],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode_plain(plain))
+}
+
+// a plain text and where it is found
+#[derive(Clone, Debug, Serialize)]
+pub struct Crack {
+    #[serde(serialize_with = "serialize_plain")]
+    pub plain: Vec<u8>,
+    pub table: Option<String>,
+    pub chain: Option<usize>,
+    pub pos: Option<usize>,
+}
+
+impl Crack {
+    pub fn new(plain: Vec<u8>, table: &str, chain: usize, pos: usize) -> Self {
+        Crack {
+            plain,
+            table: Some(table.to_owned()),
+            chain: Some(chain),
+            pos: Some(pos),
+        }
+    }
+}
+
+// chain offsets of a table searched for a hash, counted from the end of chains
+// (plain texts enumerated for brute force)
+#[derive(Clone, Debug, Serialize)]
+pub struct Coverage {
+    pub table: String,
+    pub offsets: usize,
+    pub chain_len: usize,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.offsets == self.chain_len
+    }
+}
+
+// lookup result of a single hash
+#[derive(Clone, Debug, Default)]
+pub struct HashResult {
+    pub cracks: Vec<Crack>,
+    pub false_alarms: usize,
+    pub from_potfile: bool,
+    // not measured in batch mode
+    pub time: Option<Duration>,
+    // one for each table searched
+    pub coverage: Vec<Coverage>,
+}
+
+impl HashResult {
+    pub fn from_potfile(plains: &[Vec<u8>]) -> Self {
+        HashResult {
+            cracks: plains
+                .iter()
+                .map(|plain| Crack {
+                    plain: plain.clone(),
+                    table: None,
+                    chain: None,
+                    pos: None,
+                })
+                .collect(),
+            from_potfile: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn merge(&mut self, mut other: HashResult) {
+        self.cracks.append(&mut other.cracks);
+        self.false_alarms += other.false_alarms;
+        self.coverage.append(&mut other.coverage);
+    }
+
+    // whether all offsets of all tables are searched
+    pub fn is_complete(&self) -> bool {
+        self.coverage.iter().all(|c| c.is_complete())
+    }
+
+    // (searched, total) chain offsets over all tables
+    pub fn searched(&self) -> (usize, usize) {
+        self.coverage
+            .iter()
+            .fold((0, 0), |(s, t), c| (s + c.offsets, t + c.chain_len))
+    }
+
+    // keep one crack for each plain text
+    pub fn dedup(&mut self) {
+        self.cracks.sort_by(|a, b| a.plain.cmp(&b.plain));
+        self.cracks.dedup_by(|a, b| a.plain == b.plain);
+    }
+
+    pub fn plains(&self) -> Vec<Vec<u8>> {
+        self.cracks.iter().map(|c| c.plain.clone()).collect()
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.cracks.is_empty() && !self.is_complete() {
+            // not found within budget
+            "incomplete"
+        } else if self.cracks.is_empty() {
+            "failed"
+        } else if self.from_potfile {
+            "potfile"
+        } else {
+            "cracked"
+        }
+    }
+}
+
+// limit of a lookup by deadline or cancellation, clones share the cancellation
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Budget {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Budget::default().limit(timeout)
+    }
+
+    // a budget ending no later than this one, cancelled together with it
+    pub fn limit(&self, timeout: Option<Duration>) -> Self {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        Budget {
+            deadline: match (self.deadline, deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            cancelled: self.cancelled.clone(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+// read header, charset and plain text space descriptor
+fn read_header(table: &mut File, name: &str) -> Result<(RainbowTableHeader, Vec<u8>, Vec<u8>)> {
+    let file_len = table.metadata()?.len();
+    // a short read means the file is truncated
+    let mut read_exact = |buf: &mut [u8]| {
+        table.read_exact(buf).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => Error::Truncated(name.to_owned()),
+            _ => Error::Io(e),
+        })
+    };
+
+    let mut header: RainbowTableHeader = unsafe { std::mem::zeroed() };
+
+    unsafe {
+        let header_ptr = std::slice::from_raw_parts_mut(
+            &mut header as *mut _ as *mut u8,
+            std::mem::size_of::<RainbowTableHeader>(),
+        );
+        read_exact(header_ptr)?;
+    }
+
+    if header.magic != RAINBOW_TABLE_HEADER_MAGIC {
+        return Err(Error::BadMagic(header.magic));
+    }
+    if header.is_legacy() {
+        return Err(Error::LegacyTable(name.to_owned()));
+    }
+    if header.version != RAINBOW_TABLE_VERSION {
+        return Err(Error::UnsupportedVersion(header.version));
+    }
+    if !header.is_valid() {
+        return Err(Error::InvalidHeader(format!("{}: {:?}", name, header)));
+    }
+    // check lengths against the file before allocating anything
+    match header.table_len() {
+        None => {
+            return Err(Error::InvalidHeader(format!(
+                "{}: lengths overflow: {:?}",
+                name, header
+            )))
+        }
+        Some(len) if len > file_len => return Err(Error::Truncated(name.to_owned())),
+        _ => {}
+    }
+
+    let mut charset = vec![0u8; header.charset_length as usize];
+    read_exact(charset.as_mut_slice())?;
+
+    // skip padding after charset
+    let mut space_desc = vec![0u8; padded_len(charset.len()) - charset.len()];
+    read_exact(space_desc.as_mut_slice())?;
+    space_desc.resize(header.space_length as usize, 0);
+    read_exact(space_desc.as_mut_slice())?;
+
+    Ok((header, charset, space_desc))
+}
+
+// write a table from its parts without building it in memory, chains must be sorted by tail;
+// return total length
+pub fn write_table<I: IndexInt, W: Write>(
+    output: &mut W,
+    header: &RainbowTableHeader,
+    charset: &[u8],
+    space_desc: &[u8],
+    chains: &[RainbowChain<I>],
+    metadata: &Metadata,
+) -> std::io::Result<usize> {
+    assert_eq!(I::WIDTH, header.index_width);
+    assert_eq!(chains.len() as u64, header.num_chain);
+
+    // header, charset and plain text space, each padded to 8 bytes,
+    // and chains aligned to the size of index
+    let mut head = Vec::with_capacity(header.data_offset());
+    head.extend_from_slice(unsafe {
+        std::slice::from_raw_parts(
+            (header as *const RainbowTableHeader) as *const u8,
+            std::mem::size_of::<RainbowTableHeader>(),
+        )
+    });
+    head.extend_from_slice(charset);
+    head.resize(padded_len(head.len()), 0);
+    head.extend_from_slice(space_desc);
+    head.resize(header.data_offset(), 0);
+    output.write_all(&head)?;
+
+    let chain_data = unsafe {
+        std::slice::from_raw_parts(chains.as_ptr() as *const u8, std::mem::size_of_val(chains))
+    };
+    output.write_all(chain_data)?;
+    let len = head.len() + chain_data.len();
+    if metadata.is_empty() {
+        return Ok(len);
+    }
+    let metadata = metadata.encode();
+    output.write_all(&metadata)?;
+    Ok(len + metadata.len())
+}
+
+// tail of the chain if target hash appears at the given offset, None if stopped halfway
+fn candidate_tail<I: IndexInt, S: Fn() -> bool>(
+    target_hash: &[u8],
+    space: &PlaintextSpace,
+    hasher: &ChainHasher,
+    chain_len: usize,
+    chain_offset: usize,
+    stop: S,
+) -> Option<RainbowIndex<I>> {
+    let plaintext_space_size = I::from_u128(space.size());
+    // first step: R_offset
+    let target_tail =
+        RainbowIndex::from_hash(target_hash, 0, plaintext_space_size, chain_offset as u32);
+    // remaining steps: H, R_{o+1}, H, ..., R_{l-1}
+    if chain_offset + 1 < chain_len {
+        let mut stopped = false;
+        let tail = RainbowIndex::traverse_chain(
+            target_tail,
+            space,
+            hasher,
+            chain_offset + 1,
+            chain_len - 1 - chain_offset,
+            0,
+            |_, _, _| {
+                stopped = stop();
+                stopped
+            },
+        );
+        if stopped {
+            None
+        } else {
+            Some(tail)
+        }
+    } else {
+        Some(target_tail)
+    }
+}
+
+// outcome of searching a hash at one chain offset
+enum Step {
+    Skipped,
+    Missed,
+    // None on false alarm
+    Matched(Option<Crack>),
+}
+
+// search a hash in tables of the set until the budget is exhausted,
+// the candidate tail of each offset is computed once (or taken from cache) and looked up in all tables;
+// offsets are searched from the end of chains (the cheapest) in chunks,
+// so that searched ones are contiguous when stopped early
+fn search_tables<I: IndexInt>(
+    set: &TableSet,
+    tables: &[RainbowTable],
+    target_hash: &[u8; 32],
+    stop_on_crack: bool,
+    budget: &Budget,
+    progress: &ProgressBar,
+) -> HashResult {
+    let (space, hasher) = (&set.space, &set.hasher);
+    let chain_len = tables[0].header.chain_len as usize;
+    let chunk_len = rayon::current_num_threads() * 16;
+    let cracked = AtomicBool::new(false);
+    let stopped = || budget.is_exhausted() || (stop_on_crack && cracked.load(Ordering::Relaxed));
+    let cached = set
+        .tail_cache
+        .as_ref()
+        .map(|c| c.get(target_hash))
+        .unwrap_or_default();
+
+    let mut result = HashResult::default();
+    // tails of searched offsets
+    let mut tails: Vec<u128> = Vec::new();
+    let mut searched = 0;
+    while searched < chain_len && !stopped() {
+        let chunk_end = (searched + chunk_len).min(chain_len);
+        let steps: Vec<(Option<RainbowIndex<I>>, Vec<Step>)> = (searched..chunk_end)
+            .into_par_iter()
+            .map(|i| {
+                if stopped() {
+                    return (None, vec![Step::Skipped]);
+                }
+                // offset on chain
+                let chain_offset = chain_len - 1 - i;
+                let target_tail = match cached.get(i) {
+                    Some(&tail) => {
+                        set.tail_cache.as_ref().unwrap().hit();
+                        Some(RainbowIndex(I::from_u128(tail)))
+                    }
+                    None => candidate_tail::<I, _>(
+                        target_hash,
+                        space,
+                        hasher,
+                        chain_len,
+                        chain_offset,
+                        &stopped,
+                    ),
+                };
+                let target_tail = match target_tail {
+                    Some(tail) => tail,
+                    None => return (None, vec![Step::Skipped]),
+                };
+                progress.inc(1);
+                debug!(
+                    "Searching for step {} with target tail {:#018x}\n",
+                    i, target_tail.0
+                );
+                let steps = tables
+                    .iter()
+                    .map(|table| {
+                        let step =
+                            table.match_tail(target_tail, chain_offset, target_hash, space, hasher);
+                        if let Step::Matched(Some(_)) = step {
+                            cracked.store(true, Ordering::Relaxed);
+                        }
+                        step
+                    })
+                    .collect();
+                (Some(target_tail), steps)
+            })
+            .collect();
+
+        // offsets after the first skipped one are not counted as searched
+        let mut complete = true;
+        for (tail, offset_steps) in steps {
+            for step in offset_steps {
+                match step {
+                    Step::Skipped => complete = false,
+                    Step::Missed => {}
+                    Step::Matched(Some(crack)) => result.cracks.push(crack),
+                    Step::Matched(None) => result.false_alarms += 1,
+                }
+            }
+            if complete {
+                searched += 1;
+                tails.push(tail.unwrap().0.to_u128());
+            }
+        }
+    }
+
+    if let Some(cache) = &set.tail_cache {
+        cache.insert(*target_hash, tails);
+    }
+    if searched < chain_len {
+        info!(
+            "Stopped after searching {}/{} offsets in {} tables",
+            searched,
+            chain_len,
+            tables.len()
+        );
+    }
+    for table in tables {
+        result.coverage.push(Coverage {
+            table: table.name(),
+            offsets: searched,
+            chain_len,
+        });
+    }
+    result
+}
+
+// content of a whole table file
+enum TableData {
+    Mapped(Mmap),
+    // stored as u128 to keep chains aligned, with length in bytes
+    Owned(Vec<u128>, usize),
+}
+
+impl TableData {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            TableData::Mapped(m) => m.as_ref(),
+            TableData::Owned(words, len) => unsafe {
+                std::slice::from_raw_parts(words.as_ptr() as *const u8, *len)
+            },
+        }
+    }
+}
+
+// a rainbow table mapped from file or built in memory
+pub struct RainbowTable {
+    pub path: PathBuf,
+    pub header: RainbowTableHeader,
+    pub charset: Vec<u8>,
+    pub space_desc: Vec<u8>,
+    // written after the chains
+    pub metadata: Metadata,
+    data: TableData,
+}
+
+impl RainbowTable {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let name = path.display().to_string();
+        info!("Opening rainbow table: {}", name);
+        let mut file = File::open(&path)
+            .map_err(|e| Error::Io(std::io::Error::new(e.kind(), format!("{}: {}", name, e))))?;
+        let (header, charset, space_desc) = read_header(&mut file, &name)?;
+        info!(
+            "Table header: {:?}, charset: {}",
+            header,
+            Charset::decode(&charset)
+                .map(|c| c.to_string())
+                .unwrap_or_default()
+        );
+
+        let data = unsafe { MmapOptions::new().map(&file)? };
+        // checked by read_header, unless the file has shrunk since
+        let table_len = header.table_len().unwrap_or(u64::MAX);
+        if (data.len() as u64) < table_len {
+            return Err(Error::Truncated(name));
+        }
+        let metadata = Metadata::decode(&data[table_len as usize..])
+            .map_err(|e| Error::InvalidHeader(format!("{}: {}", name, e)))?;
+
+        Ok(RainbowTable {
+            path,
+            header,
+            charset,
+            space_desc,
+            metadata,
+            data: TableData::Mapped(data),
+        })
+    }
+
+    // build a table in memory from its parts, chains must be sorted by tail
+    pub fn from_chains<I: IndexInt>(
+        name: &str,
+        header: RainbowTableHeader,
+        charset: Vec<u8>,
+        space_desc: Vec<u8>,
+        chains: &[RainbowChain<I>],
+    ) -> Self {
+        let len = header.data_offset() + std::mem::size_of_val(chains);
+        let mut words = vec![0u128; len.div_ceil(16)];
+        let mut bytes =
+            unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, len) };
+        write_table(
+            &mut bytes,
+            &header,
+            &charset,
+            &space_desc,
+            chains,
+            &Metadata::default(),
+        )
+        .unwrap();
+
+        RainbowTable {
+            path: PathBuf::from(name),
+            header,
+            charset,
+            space_desc,
+            metadata: Metadata::default(),
+            data: TableData::Owned(words, len),
+        }
+    }
+
+    // whole table in file format
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_bytes()
+    }
+
+    // write table to file or any other output, followed by metadata if any, return total length
+    pub fn write_to<W: Write>(&self, output: &mut W) -> std::io::Result<usize> {
+        let bytes = self.as_bytes();
+        let len = self.header.data_offset()
+            + self.header.num_chain as usize * 2 * (self.header.index_width / 8) as usize;
+        output.write_all(&bytes[..len])?;
+        if self.metadata.is_empty() {
+            return Ok(len);
+        }
+        let metadata = self.metadata.encode();
+        output.write_all(&metadata)?;
+        Ok(len + metadata.len())
+    }
+
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    // tables of same version, plain text space, chain length and index width can be used
+    // together, whatever their number of chains, table index and seed
+    pub fn is_compatible(&self, other: &RainbowTable) -> bool {
+        let (a, b) = (&self.header, &other.header);
+        a.version == b.version
+            && a.chain_len == b.chain_len
+            && a.index_width == b.index_width
+            && a.min_length == b.min_length
+            && a.max_length == b.max_length
+            && self.charset == other.charset
+            && self.space_desc == other.space_desc
+    }
+
+    pub fn plaintext_space(&self, wordlists: &[Arc<Wordlist>]) -> Result<PlaintextSpace> {
+        let space = if self.header.space_length > 0 {
+            PlaintextSpace::decode(&self.space_desc, wordlists)
+        } else {
+            let len_range =
+                (self.header.min_length as usize)..(self.header.max_length + 1) as usize;
+            Charset::decode(&self.charset)
+                .and_then(|c| PlaintextSpace::from_charset(&c, &len_range))
+        };
+        // lookups divide by the size of space
+        let space = space.and_then(|s| match s.size() {
+            0 => Err("Plain text space is empty".to_owned()),
+            _ => Ok(s),
+        });
+        space.map_err(|e| Error::InvalidHeader(format!("Invalid plain text space: {}", e)))
+    }
+
+    // chains sorted by tail, I must match index width of the table
+    pub fn chains<I: IndexInt>(&self) -> &[RainbowChain<I>] {
+        assert_eq!(I::WIDTH, self.header.index_width);
+        let chain_data = &self.data.as_bytes()[self.header.data_offset()..];
+        unsafe {
+            std::slice::from_raw_parts(
+                chain_data.as_ptr() as *const RainbowChain<I>,
+                self.header.num_chain as usize,
+            )
+        }
+    }
+
+    // walk a matched chain from its head, None on false alarm
+    fn verify_chain<I: IndexInt>(
+        &self,
+        chain_idx: usize,
+        chain_offset: usize,
+        target_hash: &[u8],
+        space: &PlaintextSpace,
+        hasher: &ChainHasher,
+    ) -> Option<Crack> {
+        let chain = &self.chains::<I>()[chain_idx];
+        match chain.find_match(
+            target_hash,
+            space,
+            hasher,
+            self.header.chain_len as usize,
+            0,
+        ) {
+            Some(plain) => {
+                debug!("Found plain text: {:?}\n", String::from_utf8_lossy(&plain));
+                Some(Crack::new(plain, &self.name(), chain_idx, chain_offset))
+            }
+            None => {
+                debug!("False alarm detected\n");
+                None
+            }
+        }
+    }
+
+    // look up a candidate tail in the chains and walk the matched chain
+    fn match_tail<I: IndexInt>(
+        &self,
+        target_tail: RainbowIndex<I>,
+        chain_offset: usize,
+        target_hash: &[u8],
+        space: &PlaintextSpace,
+        hasher: &ChainHasher,
+    ) -> Step {
+        let chains = self.chains::<I>();
+        match chains.binary_search(&RainbowChain {
+            head: target_tail,
+            tail: target_tail,
+        }) {
+            Ok(match_idx) => {
+                debug!(
+                    "Found matching chain {} at offset {} in {}: {:?}\n",
+                    match_idx,
+                    chain_offset,
+                    self.name(),
+                    chains[match_idx]
+                );
+                Step::Matched(self.verify_chain::<I>(
+                    match_idx,
+                    chain_offset,
+                    target_hash,
+                    space,
+                    hasher,
+                ))
+            }
+            Err(_) => Step::Missed,
+        }
+    }
+
+    // search all hashes in the chains of the table at once:
+    // compute every candidate tail, sort them and merge with the sorted chains
+    fn search_batch<I: IndexInt>(
+        &self,
+        set: &TableSet,
+        target_hashes: &[[u8; 32]],
+        budget: &Budget,
+        progress: &ProgressBar,
+    ) -> Vec<HashResult> {
+        let (space, hasher, first_hit) = (&set.space, &set.hasher, !set.all_preimages);
+        let cache = set.tail_cache.as_ref();
+        let chains = self.chains::<I>();
+        let chain_len = self.header.chain_len as usize;
+        let num_hash = target_hashes.len();
+        // offsets computed for all hashes before checking the budget again
+        let chunk_len = (rayon::current_num_threads() * 16 / num_hash.max(1)).max(1);
+
+        // first offset not searched for each hash, counted from the end of chains
+        let mut searched = vec![chain_len; num_hash];
+        // tails of each hash taken from cache and computed, in order of offsets searched
+        let mut tails: Vec<Vec<u128>> = target_hashes
+            .iter()
+            .map(|h| cache.map(|c| c.get(h)).unwrap_or_default())
+            .collect();
+        // (tail, hash number, offset) for each hash and offset
+        let mut candidates: Vec<(RainbowIndex<I>, usize, usize)> = Vec::new();
+        let mut chunk_start = 0;
+        while chunk_start < chain_len && !budget.is_exhausted() {
+            let chunk_end = (chunk_start + chunk_len).min(chain_len);
+            let chunk_tails: Vec<(usize, usize, Option<RainbowIndex<I>>)> = (chunk_start * num_hash
+                ..chunk_end * num_hash)
+                .into_par_iter()
+                .map(|n| {
+                    let (i, hash_idx) = (n / num_hash, n % num_hash);
+                    if budget.is_exhausted() {
+                        return (hash_idx, i, None);
+                    }
+                    if let Some(&tail) = tails[hash_idx].get(i) {
+                        cache.unwrap().hit();
+                        progress.inc(1);
+                        return (hash_idx, i, Some(RainbowIndex(I::from_u128(tail))));
+                    }
+                    let target_tail = candidate_tail::<I, _>(
+                        &target_hashes[hash_idx],
+                        space,
+                        hasher,
+                        chain_len,
+                        chain_len - 1 - i,
+                        || budget.is_exhausted(),
+                    );
+                    progress.inc(1);
+                    (hash_idx, i, target_tail)
+                })
+                .collect();
+            for (hash_idx, i, tail) in chunk_tails {
+                match tail {
+                    Some(tail) => {
+                        if cache.is_some() && tails[hash_idx].len() == i {
+                            tails[hash_idx].push(tail.0.to_u128());
+                        }
+                        candidates.push((tail, hash_idx, chain_len - 1 - i))
+                    }
+                    None => searched[hash_idx] = searched[hash_idx].min(i),
+                }
+            }
+            chunk_start = chunk_end;
+        }
+        for s in searched.iter_mut() {
+            *s = (*s).min(chunk_start);
+        }
+        if let Some(cache) = cache {
+            for (hash, hash_tails) in target_hashes.iter().zip(tails) {
+                cache.insert(*hash, hash_tails);
+            }
+        }
+        candidates.par_sort_unstable();
+        candidates.dedup_by_key(|c| (c.0, c.1));
+        info!("Computed {} candidate tails", candidates.len());
+
+        // merge join, both sides are sorted by tail
+        let mut matches: Vec<(usize, usize, usize)> = Vec::new();
+        let mut c = 0;
+        for (chain_idx, chain) in chains.iter().enumerate() {
+            while c < candidates.len() && candidates[c].0 < chain.tail {
+                c += 1;
+            }
+            let mut m = c;
+            while m < candidates.len() && candidates[m].0 == chain.tail {
+                matches.push((candidates[m].1, chain_idx, candidates[m].2));
+                m += 1;
+            }
+        }
+        info!("Found {} matching chains", matches.len());
+
+        // walk matched chains in parallel, in first-hit mode skipping hashes already cracked
+        let cracked: Vec<AtomicBool> = (0..num_hash).map(|_| AtomicBool::new(false)).collect();
+        let found: Vec<(usize, usize, Step)> = matches
+            .par_iter()
+            .map(|&(hash_idx, chain_idx, chain_offset)| {
+                if budget.is_exhausted() || (first_hit && cracked[hash_idx].load(Ordering::Relaxed))
+                {
+                    return (hash_idx, chain_offset, Step::Skipped);
+                }
+                let crack = self.verify_chain::<I>(
+                    chain_idx,
+                    chain_offset,
+                    &target_hashes[hash_idx],
+                    space,
+                    hasher,
+                );
+                if crack.is_some() {
+                    cracked[hash_idx].store(true, Ordering::Relaxed);
+                }
+                (hash_idx, chain_offset, Step::Matched(crack))
+            })
+            .collect();
+
+        let mut results = vec![HashResult::default(); num_hash];
+        for (hash_idx, chain_offset, step) in found {
+            match step {
+                Step::Matched(Some(crack)) => results[hash_idx].cracks.push(crack),
+                Step::Matched(None) => results[hash_idx].false_alarms += 1,
+                Step::Missed => {}
+                Step::Skipped => {
+                    // an unverified match leaves its offset unsearched
+                    let i = chain_len - 1 - chain_offset;
+                    searched[hash_idx] = searched[hash_idx].min(i);
+                }
+            }
+        }
+        for (result, offsets) in results.iter_mut().zip(searched) {
+            result.coverage.push(Coverage {
+                table: self.name(),
+                offsets,
+                chain_len,
+            });
+        }
+        results
+    }
+}
+
+// tables with the same parameters, searched together
+pub struct TableSet {
+    pub tables: Vec<RainbowTable>,
+    pub space: PlaintextSpace,
+    // built-in SM3 unless replaced
+    pub hasher: ChainHasher,
+    // keep searching after a hash is cracked instead of stopping at the first hit
+    pub all_preimages: bool,
+    // candidate tails reused across lookups
+    pub tail_cache: Option<TailCache>,
+}
+
+impl TableSet {
+    pub fn open<P: AsRef<Path>>(paths: &[P], wordlists: &[Arc<Wordlist>]) -> Result<Self> {
+        let tables = paths
+            .iter()
+            .map(RainbowTable::open)
+            .collect::<Result<Vec<_>>>()?;
+        TableSet::from_tables(tables, wordlists)
+    }
+
+    pub fn from_tables(tables: Vec<RainbowTable>, wordlists: &[Arc<Wordlist>]) -> Result<Self> {
+        let first = tables
+            .first()
+            .ok_or_else(|| Error::InvalidInput("No rainbow table given".to_owned()))?;
+        // check header consistency
+        for t in &tables[1..] {
+            if !first.is_compatible(t) {
+                return Err(Error::InconsistentTables(t.name()));
+            }
+        }
+        let space = first.plaintext_space(wordlists)?;
+        info!(
+            "Plain text count: {:?}, space size: {}, index width: {}",
+            space.offsets,
+            space.size(),
+            first.header.index_width
+        );
+        Ok(TableSet {
+            tables,
+            hasher: ChainHasher::new(&space),
+            space,
+            all_preimages: false,
+            tail_cache: None,
+        })
+    }
+
+    pub fn header(&self) -> &RainbowTableHeader {
+        &self.tables[0].header
+    }
+
+    // short summary of parameters shared by the tables
+    pub fn description(&self) -> String {
+        let header = self.header();
+        let space = match Charset::decode(&self.tables[0].charset) {
+            Ok(charset) if header.space_length == 0 => format!(
+                "charset {}, length {}-{}",
+                charset, header.min_length, header.max_length
+            ),
+            _ => format!("{} plain texts", self.space.size()),
+        };
+        let num_chain: u64 = self.tables.iter().map(|t| t.header.num_chain).sum();
+        format!(
+            "{} tables of {} chains in total, chain length {}, {}",
+            self.tables.len(),
+            num_chain,
+            header.chain_len,
+            space
+        )
+    }
+
+    // identifies tables sharing candidate tails:
+    // plain text space, chain length and reduction functions (by table version)
+    pub fn cache_key(&self) -> [u8; 32] {
+        let header = self.header();
+        let first = &self.tables[0];
+        let mut buf = Vec::new();
+        for v in &[
+            RAINBOW_TABLE_VERSION,
+            header.chain_len,
+            header.index_width,
+            header.min_length as u64,
+            header.max_length as u64,
+            first.charset.len() as u64,
+            first.space_desc.len() as u64,
+        ] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        buf.extend_from_slice(&first.charset);
+        buf.extend_from_slice(&first.space_desc);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&MY_SM3(&buf));
+        key
+    }
+
+    // use candidate tails cached in file, which is created when saving if missing
+    pub fn open_tail_cache<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let cache = TailCache::open(path, self.cache_key(), self.header().index_width)?;
+        self.tail_cache = Some(cache);
+        Ok(())
+    }
+
+    // search a hash in one table within budget, progress is increased once per chain position
+    pub fn search_hash(
+        &self,
+        table: &RainbowTable,
+        target_hash: &[u8; 32],
+        budget: &Budget,
+        progress: &ProgressBar,
+    ) -> HashResult {
+        self.search_in(
+            std::slice::from_ref(table),
+            target_hash,
+            false,
+            budget,
+            progress,
+        )
+    }
+
+    // search a hash in all tables at once from the cheapest offset,
+    // stopping at the first crack unless all_preimages is set,
+    // progress is increased once per chain position
+    pub fn search_all(
+        &self,
+        target_hash: &[u8; 32],
+        budget: &Budget,
+        progress: &ProgressBar,
+    ) -> HashResult {
+        let first_hit = !self.all_preimages;
+        self.search_in(&self.tables, target_hash, first_hit, budget, progress)
+    }
+
+    fn search_in(
+        &self,
+        tables: &[RainbowTable],
+        target_hash: &[u8; 32],
+        stop_on_crack: bool,
+        budget: &Budget,
+        progress: &ProgressBar,
+    ) -> HashResult {
+        if self.header().index_width == u64::WIDTH {
+            search_tables::<u64>(self, tables, target_hash, stop_on_crack, budget, progress)
+        } else {
+            search_tables::<u128>(self, tables, target_hash, stop_on_crack, budget, progress)
+        }
+    }
+
+    // search many hashes in one table in a single pass,
+    // verifying only until the first crack of each hash unless all_preimages is set
+    pub fn search_batch(
+        &self,
+        table: &RainbowTable,
+        target_hashes: &[[u8; 32]],
+        budget: &Budget,
+        progress: &ProgressBar,
+    ) -> Vec<HashResult> {
+        if self.header().index_width == u64::WIDTH {
+            table.search_batch::<u64>(self, target_hashes, budget, progress)
+        } else {
+            table.search_batch::<u128>(self, target_hashes, budget, progress)
+        }
+    }
+
+    // distinct plain texts of a hash found in the tables, only the first unless all_preimages is set
+    pub fn lookup(&self, hash: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut target_hash = [0u8; 32];
+        if hash.len() != target_hash.len() {
+            return Err(Error::InvalidHash(format!(
+                "Hash must be 32 bytes, got {}",
+                hash.len()
+            )));
+        }
+        target_hash.copy_from_slice(hash);
+
+        let progress = ProgressBar::hidden();
+        let mut result = self.search_all(&target_hash, &Budget::default(), &progress);
+        result.dedup();
+        Ok(result.cracks.into_iter().map(|c| c.plain).collect())
+    }
+}
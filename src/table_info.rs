@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use clap::Clap;
+use log::*;
+use sm3::error::Error;
+use sm3::rainbow::Charset;
+use sm3::table::RainbowTable;
+use sm3::wordlist::Wordlist;
+
+#[derive(Clap, Debug)]
+#[clap(
+    name = "table_info",
+    version = "0.1",
+    author = "Shengqi Chen <i@harrychen.xyz>",
+    about = "Print parameters and metadata of rainbow tables of SM3 hash algorithm"
+)]
+pub struct InfoOptions {
+    #[clap(required = true)]
+    pub table_files: Vec<String>,
+    #[clap(short = 'w', long)]
+    /// Wordlists used by tables (matched by content digest), to count their plain texts
+    pub wordlist: Vec<String>,
+}
+
+fn print_table(table: &RainbowTable, wordlists: &[Arc<Wordlist>]) {
+    let header = &table.header;
+    println!("{}:", table.name());
+    println!("  version: {}", header.version);
+    println!(
+        "  chains: {}, chain length: {}, table index: {}, seed: {}",
+        header.num_chain, header.chain_len, header.table_index, header.seed
+    );
+    if header.space_length == 0 {
+        match Charset::decode(&table.charset) {
+            Ok(charset) => println!(
+                "  charset: {}, length: {}-{}",
+                charset, header.min_length, header.max_length
+            ),
+            Err(e) => println!("  charset: {}", e),
+        }
+    }
+    match table.plaintext_space(wordlists) {
+        Ok(space) => println!("  plain texts: {}", space.size()),
+        Err(e) => println!("  plain texts: unknown ({})", e),
+    }
+    println!("  index width: {}", header.index_width);
+
+    let metadata = &table.metadata;
+    if metadata.is_empty() {
+        println!("  no metadata");
+        return;
+    }
+    if let Some(generator) = &metadata.generator {
+        println!("  generator: {}", generator);
+    }
+    if let Some(created) = metadata.created {
+        println!("  created: {} (unix time)", created);
+    }
+    if let Some(host) = &metadata.host {
+        println!("  host: {}", host);
+    }
+    if let Some(duration) = metadata.duration_ms {
+        println!("  generation time: {:.3}s", duration as f64 / 1000.0);
+    }
+    if let Some(generated) = metadata.chains_generated {
+        println!("  chains generated: {}", generated);
+    }
+    for (key, value) in &metadata.tags {
+        println!("  tag {}: {}", key, value);
+    }
+    for (t, value) in &metadata.unknown {
+        println!("  unknown record {}: {} bytes", t, value.len());
+    }
+}
+
+fn main() {
+    env_logger::builder().init();
+    let opts: InfoOptions = InfoOptions::parse();
+    info!("Program options: {:?}", opts);
+    let wordlists: Vec<_> = opts
+        .wordlist
+        .iter()
+        .map(|f| {
+            Arc::new(Wordlist::load(f).unwrap_or_else(|e| {
+                error!("Cannot read wordlist {}: {}", f, e);
+                std::process::exit(Error::Io(e).exit_code());
+            }))
+        })
+        .collect();
+
+    // report every table, exiting with the code of the last error
+    let mut exit_code = 0;
+    for f in &opts.table_files {
+        match RainbowTable::open(f) {
+            Ok(table) => print_table(&table, &wordlists),
+            Err(e) => {
+                error!("Cannot open rainbow table {}: {}", f, e);
+                exit_code = e.exit_code();
+            }
+        }
+    }
+    std::process::exit(exit_code);
+}
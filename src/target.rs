@@ -0,0 +1,92 @@
+// hashes to crack, parsed from command line or hash lists
+
+use std::io::BufRead;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Target {
+    pub user: Option<String>,
+    pub hash: [u8; 32],
+    pub salt: Option<String>,
+}
+
+pub(crate) fn parse_hash(s: &str) -> Option<[u8; 32]> {
+    let mut hash = [0u8; 32];
+    if s.len() == 64 && hex::decode_to_slice(s, &mut hash).is_ok() {
+        Some(hash)
+    } else {
+        None
+    }
+}
+
+impl Target {
+    // accept "hash", "user:hash" or "hash:salt"
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.trim().split(':').collect();
+        match fields.as_slice() {
+            [h] => match parse_hash(h) {
+                Some(hash) => Ok(Target {
+                    user: None,
+                    hash,
+                    salt: None,
+                }),
+                None => Err(format!("Invalid hash: {}", h)),
+            },
+            [a, b] => match (parse_hash(a), parse_hash(b)) {
+                (Some(hash), _) => Ok(Target {
+                    user: None,
+                    hash,
+                    salt: Some(b.to_string()),
+                }),
+                (None, Some(hash)) => Ok(Target {
+                    user: Some(a.to_string()),
+                    hash,
+                    salt: None,
+                }),
+                _ => Err(format!("No valid hash in {}", line.trim())),
+            },
+            _ => Err(format!("Too many fields in {}", line.trim())),
+        }
+    }
+
+    pub fn hash_str(&self) -> String {
+        hex::encode(self.hash)
+    }
+
+    // target as given in hash lists, without salt
+    pub fn line(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}:{}", user, self.hash_str()),
+            None => self.hash_str(),
+        }
+    }
+
+    // how the target is shown in results
+    pub fn label(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{} ({})", user, self.hash_str()),
+            None => self.hash_str(),
+        }
+    }
+}
+
+// line number and error of a malformed target line
+pub type Malformed = (usize, String);
+
+// read one target per line, empty lines and lines starting with '#' are ignored
+// return parsed targets and malformed lines
+pub fn read_targets<R: BufRead>(reader: R) -> std::io::Result<(Vec<Target>, Vec<Malformed>)> {
+    let mut targets = Vec::new();
+    let mut malformed = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match Target::parse(line) {
+            Ok(t) => targets.push(t),
+            Err(e) => malformed.push((i + 1, e)),
+        }
+    }
+    Ok((targets, malformed))
+}
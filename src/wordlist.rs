@@ -0,0 +1,189 @@
+// wordlists and mangling rules as plain text space
+
+use std::path::Path;
+
+use crate::my_sm3_impl::my_hash_impl_inplace;
+
+// words read from a file, identified by the SM3 digest of file content
+#[derive(Clone)]
+pub struct Wordlist {
+    pub words: Vec<Vec<u8>>,
+    pub digest: [u8; 32],
+}
+
+impl std::fmt::Debug for Wordlist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wordlist")
+            .field("words", &self.words.len())
+            .field("digest", &hex::encode(self.digest))
+            .finish()
+    }
+}
+
+impl PartialEq for Wordlist {
+    fn eq(&self, other: &Self) -> bool {
+        self.digest == other.digest
+    }
+}
+
+impl Eq for Wordlist {}
+
+impl Wordlist {
+    // one word per line, empty lines are ignored
+    pub fn from_bytes(content: &[u8]) -> Self {
+        let mut digest = [0u8; 32];
+        my_hash_impl_inplace(content, content.len(), &mut digest);
+        let words = content
+            .split(|&c| c == b'\n')
+            .map(|w| w.strip_suffix(b"\r").unwrap_or(w))
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_vec())
+            .collect();
+        Wordlist { words, digest }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Wordlist::from_bytes(&std::fs::read(path)?))
+    }
+}
+
+// single step of a rule
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RuleOp {
+    // ':' keep word unchanged
+    Noop,
+    // 'l' lowercase all letters
+    Lower,
+    // 'u' uppercase all letters
+    Upper,
+    // 'c' uppercase first letter, lowercase the rest
+    Capitalize,
+    // 't' toggle case of all letters
+    Toggle,
+    // 'e' leetspeak: a -> 4, e -> 3, i -> 1, o -> 0, s -> 5, t -> 7
+    Leet,
+    // 'dN' append N digits, has 10^N variants
+    AppendDigits(u32),
+}
+
+// sequence of operations applied to a word in order, e.g. "cd2" (capitalize and append 2 digits)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    pub text: String,
+    pub ops: Vec<RuleOp>,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = text.as_bytes();
+        let mut ops = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let op = match bytes[i] {
+                b':' => RuleOp::Noop,
+                b'l' => RuleOp::Lower,
+                b'u' => RuleOp::Upper,
+                b'c' => RuleOp::Capitalize,
+                b't' => RuleOp::Toggle,
+                b'e' => RuleOp::Leet,
+                b'd' => {
+                    i += 1;
+                    match bytes.get(i) {
+                        Some(&c @ b'1'..=b'9') => RuleOp::AppendDigits((c - b'0') as u32),
+                        _ => return Err(format!("Rule {}: 'd' must be followed by 1-9", text)),
+                    }
+                }
+                c => return Err(format!("Rule {}: unknown operation {}", text, c as char)),
+            };
+            ops.push(op);
+            i += 1;
+        }
+        if ops.is_empty() {
+            return Err("Rule is empty".to_owned());
+        }
+        let rule = Rule {
+            text: text.to_owned(),
+            ops,
+        };
+        if rule.variants().is_none() {
+            return Err(format!("Rule {}: too many variants (exceeds 2^128)", text));
+        }
+        Ok(rule)
+    }
+
+    // number of different outputs for a single word, None on overflow
+    pub fn variants(&self) -> Option<u128> {
+        self.ops.iter().try_fold(1u128, |acc, op| match op {
+            RuleOp::AppendDigits(n) => 10u128.checked_pow(*n).and_then(|v| acc.checked_mul(v)),
+            _ => Some(acc),
+        })
+    }
+
+    // max number of bytes appended to a word
+    pub fn max_extra_len(&self) -> usize {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                RuleOp::AppendDigits(n) => *n as usize,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // write mangled word to output, return its length
+    pub fn apply(&self, word: &[u8], mut variant: u128, output: &mut [u8]) -> usize {
+        let mut len = word.len();
+        output[..len].copy_from_slice(word);
+        for op in &self.ops {
+            let text = &mut output[..len];
+            match op {
+                RuleOp::Noop => {}
+                RuleOp::Lower => text.make_ascii_lowercase(),
+                RuleOp::Upper => text.make_ascii_uppercase(),
+                RuleOp::Capitalize => {
+                    text.make_ascii_lowercase();
+                    if let Some(c) = text.first_mut() {
+                        c.make_ascii_uppercase();
+                    }
+                }
+                RuleOp::Toggle => {
+                    for c in text.iter_mut() {
+                        if c.is_ascii_alphabetic() {
+                            *c ^= 0x20;
+                        }
+                    }
+                }
+                RuleOp::Leet => {
+                    for c in text.iter_mut() {
+                        *c = match *c {
+                            b'a' | b'A' => b'4',
+                            b'e' | b'E' => b'3',
+                            b'i' | b'I' => b'1',
+                            b'o' | b'O' => b'0',
+                            b's' | b'S' => b'5',
+                            b't' | b'T' => b'7',
+                            c => c,
+                        };
+                    }
+                }
+                RuleOp::AppendDigits(n) => {
+                    // most significant digit first, so that variants are in numeric order
+                    let n = *n as usize;
+                    let mut digits = variant % 10u128.pow(n as u32);
+                    variant /= 10u128.pow(n as u32);
+                    for i in (0..n).rev() {
+                        output[len + i] = b'0' + (digits % 10) as u8;
+                        digits /= 10;
+                    }
+                    len += n;
+                }
+            }
+        }
+        len
+    }
+}
+
+// parse comma-separated rules, e.g. ":,c,cd2,e"
+pub fn parse_rules(rules: &str) -> Result<Vec<Rule>, String> {
+    rules.split(',').map(Rule::parse).collect()
+}
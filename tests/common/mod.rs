@@ -0,0 +1,78 @@
+// fixtures shared by integration tests, each test file uses only some of them
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use sm3::builder::{TableBuilder, TableParams};
+use sm3::rainbow::Charset;
+use sm3::table::{RainbowTable, TableSet};
+
+// a tiny table of [a-h]{1,4} (4680 plain texts), generated in milliseconds
+pub fn small_params() -> TableParams {
+    let mut params = TableParams::from_charset(Charset::from_chars("abcdefgh"), 1, 4).unwrap();
+    params.num_chain = 20;
+    params.chain_len = 50;
+    params
+}
+
+pub fn small_table(params: &TableParams) -> RainbowTable {
+    TableBuilder::new(params.clone()).build().unwrap()
+}
+
+pub fn small_set(params: &TableParams) -> TableSet {
+    TableSet::from_tables(vec![small_table(params)], &[]).unwrap()
+}
+
+pub fn hash_of(plain: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sm3::MY_SM3(plain));
+    hash
+}
+
+// plain text at the head of the first chain, which is always found, and its hash
+pub fn head_plaintext(tables: &TableSet) -> (Vec<u8>, [u8; 32]) {
+    let head = tables.tables[0].chains::<u64>()[0].head;
+    let mut plaintext = vec![0u8; tables.space.max_len()];
+    let len = head.to_plaintext(&tables.space, &mut plaintext);
+    plaintext.truncate(len);
+    let hash = hash_of(&plaintext);
+    (plaintext, hash)
+}
+
+// temporary directory removed when dropped; tests run in parallel, so each uses its own name
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("sm3_test_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+
+    // write tables of index 0..count, return their paths
+    pub fn write_tables(&self, prefix: &str, params: &TableParams, count: u64) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| {
+                let mut params = params.clone();
+                params.table_index = i;
+                let path = self.join(format!("{}{}.dat", prefix, i));
+                let mut file = std::fs::File::create(&path).unwrap();
+                TableBuilder::new(params).write_to(&mut file).unwrap();
+                path
+            })
+            .collect()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
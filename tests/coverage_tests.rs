@@ -0,0 +1,69 @@
+mod common;
+
+#[cfg(test)]
+mod coverage_tests {
+
+    use super::*;
+    use common::{hash_of, small_params, TempDir};
+    use sm3::rainbow::RainbowIndex;
+    use sm3::table::TableSet;
+
+    #[test]
+    fn test_chain_members_found() {
+        let params = small_params();
+        let dir = TempDir::new("chain_members");
+        let set = TableSet::open(&dir.write_tables("table_", &params, 2), &[]).unwrap();
+
+        // plant plain texts of every position of some chains of each table
+        let mut planted = Vec::new();
+        let mut num_planted_chain = 0;
+        for table in &set.tables {
+            for chain in table.chains::<u64>().iter().step_by(5) {
+                num_planted_chain += 1;
+                let tail = RainbowIndex::traverse_chain(
+                    chain.head,
+                    &set.space,
+                    &set.hasher,
+                    0,
+                    params.chain_len as usize,
+                    0,
+                    |_, plaintext, len| {
+                        planted.push(plaintext[..len].to_vec());
+                        false
+                    },
+                );
+                assert_eq!(tail, chain.tail);
+            }
+        }
+        assert!(num_planted_chain >= 2);
+        assert_eq!(planted.len(), num_planted_chain * params.chain_len as usize);
+
+        for plain in &planted {
+            let hash = hash_of(plain);
+            let found = set.lookup(&hash).unwrap();
+            assert!(!found.is_empty(), "{:?} not found", plain);
+            // other preimages may be found first, but each must hash to the target
+            assert!(found.iter().all(|p| hash_of(p) == hash));
+        }
+    }
+
+    #[test]
+    fn test_no_false_positives() {
+        let mut params = small_params();
+        params.seed = 2;
+        let dir = TempDir::new("false_positives");
+        let mut set = TableSet::open(&dir.write_tables("table_", &params, 1), &[]).unwrap();
+        set.all_preimages = true;
+
+        // plain texts outside of the space, and random hashes
+        let mut hashes: Vec<[u8; 32]> = ["xyz", "abcdefgh", "", "ABC"]
+            .iter()
+            .map(|p| hash_of(p.as_bytes()))
+            .collect();
+        hashes.extend((0..16).map(|_| rand::random::<[u8; 32]>()));
+        for hash in &hashes {
+            assert!(set.lookup(hash).unwrap().is_empty());
+        }
+        assert!(set.lookup(&[0u8; 31]).is_err());
+    }
+}
@@ -0,0 +1,536 @@
+mod common;
+
+#[cfg(test)]
+mod rainbow_tests {
+
+    use super::*;
+    use common::{head_plaintext, small_params, small_set, small_table, TempDir};
+    use sm3::brute::{brute_force, uncovered_space};
+    use sm3::builder::{TableBuilder, TableParams};
+    use sm3::catalog::Catalog;
+    use sm3::error::Error;
+    use sm3::metadata::Metadata;
+    use sm3::rainbow::{
+        index_width_for, Charset, PlaintextIter, PlaintextSpace, RainbowIndex, Segment,
+    };
+    use sm3::table::{Budget, RainbowTable};
+    use sm3::wordlist::{parse_rules, Wordlist};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_mask() {
+        let custom = [Some("ab?d".to_owned()), None, None, None];
+        let positions = sm3::mask::parse_mask("?u?1x??", &custom).unwrap();
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0], Charset::from_bytes(sm3::mask::UPPER));
+        assert_eq!(positions[1], Charset::from_bytes(b"ab0123456789"));
+        assert_eq!(positions[2], Charset::from_bytes(b"x"));
+        assert_eq!(positions[3], Charset::from_bytes(b"?"));
+        assert!(sm3::mask::parse_mask("?2", &custom).is_err());
+        assert!(sm3::mask::parse_mask("?x", &custom).is_err());
+        assert!(sm3::mask::parse_mask("abc?", &custom).is_err());
+        assert_eq!(sm3::mask::parse_mask("?d{3}x", &custom).unwrap().len(), 4);
+        assert!(sm3::mask::parse_mask("?d{0}", &custom).is_err());
+        assert!(sm3::mask::parse_mask("?d{3", &custom).is_err());
+    }
+
+    #[test]
+    fn test_mask_to_plaintext() {
+        let space = PlaintextSpace::from_masks(&["?u?l?d".to_owned()], &[]).unwrap();
+        assert_eq!(space.size(), 26 * 26 * 10);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u128>(0).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"Aa0");
+        let len = RainbowIndex::<u128>(space.size() - 1).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"Zz9");
+        let len =
+            RainbowIndex::<u128>(1 + 26 * 2 + 26 * 26 * 3).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"Bc3");
+        // descriptor stored in header
+        assert_eq!(
+            PlaintextSpace::decode(&space.encode(), &[]).ok(),
+            Some(space)
+        );
+    }
+
+    #[test]
+    fn test_segments_to_plaintext() {
+        let masks = ["?d{2}".to_owned(), "?l?d".to_owned()];
+        let space = PlaintextSpace::from_masks(&masks, &[]).unwrap();
+        assert_eq!(space.offsets, vec![0, 100, 360]);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u128>(99).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"99");
+        let len = RainbowIndex::<u128>(100).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"a0");
+        assert_eq!(
+            PlaintextSpace::decode(&space.encode(), &[]).ok(),
+            Some(space)
+        );
+
+        // charset over length range keeps the cumulative layout
+        let space = PlaintextSpace::from_charset(&Charset::from_bytes(b"ab"), &(2..4)).unwrap();
+        assert_eq!(space.offsets, vec![0, 4, 12]);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u128>(5).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"baa");
+    }
+
+    #[test]
+    fn test_wordlist_to_plaintext() {
+        let wordlist = Arc::new(Wordlist::from_bytes(b"hello\r\n\nsecret\n"));
+        assert_eq!(wordlist.words, vec![b"hello".to_vec(), b"secret".to_vec()]);
+        let rules = parse_rules(":,c,ed2,t").unwrap();
+        assert!(parse_rules("x").is_err());
+        assert!(parse_rules("d").is_err());
+        let space = PlaintextSpace::from_segments(vec![Segment::Wordlist {
+            wordlist: wordlist.clone(),
+            rules,
+        }])
+        .unwrap();
+        assert_eq!(space.size(), 2 * (1 + 1 + 100 + 1));
+        assert_eq!(space.max_len(), 8);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let expected: &[(u128, &[u8])] = &[
+            (1, b"secret"),
+            (2, b"Hello"),
+            (4, b"h3ll000"),
+            (5, b"53cr3700"),
+            (4 + 2 * 42 + 1, b"53cr3742"),
+            (205, b"SECRET"),
+        ];
+        for (index, text) in expected {
+            let len = RainbowIndex::<u128>(*index).to_plaintext(&space, &mut plaintext);
+            assert_eq!(&plaintext[..len], *text);
+        }
+        assert_eq!(
+            PlaintextSpace::decode(&space.encode(), &[wordlist]).ok(),
+            Some(space.clone())
+        );
+        assert!(PlaintextSpace::decode(&space.encode(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_rule_overflow() {
+        // 10^27 variants need more than 64 bits
+        let rules = parse_rules("d9d9d9").unwrap();
+        assert_eq!(rules[0].variants(), Some(10u128.pow(27)));
+        let wordlist = Arc::new(Wordlist::from_bytes(b"a\nb\n"));
+        let space = PlaintextSpace::from_segments(vec![Segment::Wordlist {
+            wordlist: wordlist.clone(),
+            rules,
+        }])
+        .unwrap();
+        assert_eq!(space.size(), 2 * 10u128.pow(27));
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u128>(space.size() - 1).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"b999999999999999999999999999");
+        // 10^45 variants exceed 2^128
+        assert!(parse_rules("d9d9d9d9d9").is_err());
+        // 400 words of 10^36 variants each
+        let words: Vec<u8> = (0..400)
+            .flat_map(|i| format!("{}\n", i).into_bytes())
+            .collect();
+        let result = PlaintextSpace::from_segments(vec![Segment::Wordlist {
+            wordlist: Arc::new(Wordlist::from_bytes(&words)),
+            rules: parse_rules("d9d9d9d9").unwrap(),
+        }]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_large_space() {
+        // 95^10 > 2^64
+        let printable = Charset::from_bytes(&(0x20..0x7f).collect::<Vec<u8>>());
+        let space = PlaintextSpace::from_charset(&printable, &(1..11)).unwrap();
+        assert!(space.size() > u64::MAX as u128);
+        assert_eq!(index_width_for(space.size()), 128);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u128>(space.size() - 1).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"~~~~~~~~~~");
+        let index = RainbowIndex::<u128>::from_hash(&[0xffu8; 32], 0, space.size(), 1);
+        assert!(index.0 < space.size());
+        // 95^20 > 2^128
+        assert!(PlaintextSpace::from_charset(&printable, &(1..21)).is_err());
+    }
+
+    #[test]
+    fn test_multibyte_charset() {
+        let custom = [Some("张王?d".to_owned())];
+        let positions = sm3::mask::parse_mask("李?1", &custom).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[1].len(), 12);
+        let charset = Charset::from_tokens(&["zhang", "李", "!"]);
+        assert_eq!(Charset::decode(&charset.encode()), Ok(charset.clone()));
+        let space = PlaintextSpace::from_charset(&charset, &(1..3)).unwrap();
+        assert_eq!(space.size(), 3 + 9);
+        assert_eq!(space.max_len(), 10);
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u64>(3 + 1).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], "李zhang".as_bytes());
+        let len = RainbowIndex::<u64>(3 + 2 + 3).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], "!李".as_bytes());
+        let space = PlaintextSpace::from_masks(&["?1?1".to_owned()], &custom).unwrap();
+        assert_eq!(PlaintextSpace::decode(&space.encode(), &[]), Ok(space));
+    }
+
+    #[test]
+    fn test_table_builder() {
+        let mut params = TableParams::from_charset(Charset::from_chars("abc"), 1, 3).unwrap();
+        params.num_chain = 5;
+        params.chain_len = 4;
+        let table = TableBuilder::new(params.clone()).build().unwrap();
+        assert_eq!(table.header, params.header());
+        assert_eq!(table.chains::<u64>().len(), 5);
+        assert!(table
+            .chains::<u64>()
+            .windows(2)
+            .all(|w| w[0].tail < w[1].tail));
+
+        let mut output = Vec::new();
+        let len = TableBuilder::new(params.clone())
+            .write_to(&mut output)
+            .unwrap();
+        assert_eq!(len, output.len());
+        assert_eq!(&output[..], table.as_bytes());
+        // prefix states of the built-in implementation give the same chains
+        let plain = TableBuilder::new(params.clone())
+            .hasher(sm3::OPENSSL_SM3)
+            .build()
+            .unwrap();
+        assert_eq!(plain.chains::<u64>(), table.chains::<u64>());
+
+        let cancelled = TableBuilder::new(params).progress(|_, _| false).build();
+        assert!(matches!(cancelled, Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn test_small_space() {
+        let wordlist = Arc::new(Wordlist::from_bytes(b"hello\nsecret\n"));
+        let space = PlaintextSpace::from_segments(vec![Segment::Wordlist {
+            wordlist,
+            rules: parse_rules(":,c").unwrap(),
+        }])
+        .unwrap();
+        assert_eq!(space.size(), 4);
+        // indices past the space wrap around instead of panicking
+        let mut plaintext = vec![0u8; space.max_len()];
+        let len = RainbowIndex::<u64>(4 + 1).to_plaintext(&space, &mut plaintext);
+        assert_eq!(&plaintext[..len], b"secret");
+
+        let mut params = TableParams::from_space(space);
+        params.num_chain = 20;
+        params.chain_len = 2;
+        let result = TableBuilder::new(params.clone()).build();
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+        // sequential heads 4-6 are out of space, random ones are not
+        params.num_chain = 2;
+        params.table_index = 2;
+        assert!(matches!(params.validate(), Err(Error::InvalidInput(_))));
+        params.random_head = true;
+        assert!(params.validate().is_ok());
+
+        // empty spaces and length ranges are rejected
+        assert!(PlaintextSpace::from_segments(Vec::new()).is_err());
+        let empty = Segment::Wordlist {
+            wordlist: Arc::new(Wordlist::from_bytes(b"\n")),
+            rules: parse_rules(":").unwrap(),
+        };
+        assert!(PlaintextSpace::from_segments(vec![empty]).is_err());
+        for (min, max) in [(0, 2), (3, 2)] {
+            let params = TableParams::from_charset(Charset::from_chars("ab"), min, max);
+            assert!(matches!(params, Err(Error::InvalidInput(_))));
+        }
+    }
+
+    #[test]
+    fn test_not_enough_tails() {
+        let mut params = TableParams::from_charset(Charset::from_chars("ab"), 1, 2).unwrap();
+        params.num_chain = 6;
+        params.chain_len = 20;
+        let result = TableBuilder::new(params).build();
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let mut params = TableParams::from_charset(Charset::from_chars("abc"), 1, 3).unwrap();
+        params.num_chain = 5;
+        params.chain_len = 4;
+        let table = small_table(&params);
+        let dir = TempDir::new("corrupt");
+        let path = dir.join("corrupt.dat");
+        // overwrite a u64 field of the header at offset
+        let open_with = |offset: usize, value: u64| {
+            let mut bytes = table.as_bytes().to_vec();
+            bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&path, &bytes).unwrap();
+            RainbowTable::open(&path)
+        };
+        // num_chain, charset_length, space_length
+        assert!(matches!(open_with(16, 6), Err(Error::Truncated(_))));
+        assert!(matches!(
+            open_with(16, u64::MAX / 2),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(matches!(open_with(48, 1 << 40), Err(Error::Truncated(_))));
+        assert!(matches!(
+            open_with(48, u64::MAX - 3),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            open_with(64, u64::MAX),
+            Err(Error::InvalidHeader(_))
+        ));
+        assert!(open_with(16, 5).is_ok());
+        // min_length above max_length
+        assert!(matches!(
+            open_with(40, 3 | (2 << 32)),
+            Err(Error::InvalidHeader(_))
+        ));
+        // tables without version have num_chain in its place
+        assert!(matches!(open_with(8, 100), Err(Error::LegacyTable(_))));
+        std::fs::write(&path, &table.as_bytes()[..100]).unwrap();
+        assert!(matches!(
+            RainbowTable::open(&path),
+            Err(Error::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn test_lookup_budget() {
+        let tables = small_set(&small_params());
+        let hash = [0u8; 32];
+        let progress = indicatif::ProgressBar::hidden();
+
+        let result = tables.search_hash(&tables.tables[0], &hash, &Budget::default(), &progress);
+        assert!(result.is_complete());
+        assert_eq!(result.searched(), (50, 50));
+        assert_eq!(result.status(), "failed");
+
+        // cancelling a budget also cancels budgets derived from it
+        let budget = Budget::default();
+        let hash_budget = budget.limit(Some(std::time::Duration::from_secs(60)));
+        budget.cancel();
+        let result = tables.search_hash(&tables.tables[0], &hash, &hash_budget, &progress);
+        assert_eq!(result.searched(), (0, 50));
+        assert_eq!(result.status(), "incomplete");
+        let results =
+            tables.search_batch(&tables.tables[0], &[hash, hash], &hash_budget, &progress);
+        assert!(results.iter().all(|r| r.status() == "incomplete"));
+    }
+
+    #[test]
+    fn test_first_hit() {
+        let mut tables = small_set(&small_params());
+
+        // plain text at the head of a chain is found at the most expensive offset
+        let (plaintext, hash) = head_plaintext(&tables);
+        let progress = indicatif::ProgressBar::hidden();
+
+        let result = tables.search_all(&hash, &Budget::default(), &progress);
+        assert_eq!(result.status(), "cracked");
+        assert_eq!(result.cracks[0].plain, plaintext);
+        assert_eq!(tables.lookup(&hash).unwrap(), vec![plaintext.clone()]);
+
+        tables.all_preimages = true;
+        let result = tables.search_all(&hash, &Budget::default(), &progress);
+        assert!(result.is_complete());
+        assert!(result.cracks.iter().all(|c| c.plain == plaintext));
+        let results =
+            tables.search_batch(&tables.tables[0], &[hash], &Budget::default(), &progress);
+        assert_eq!(results[0].status(), "cracked");
+    }
+
+    #[test]
+    fn test_tail_cache() {
+        let dir = TempDir::new("tail_cache");
+        let path = dir.join("cache.tails");
+        let mut params = small_params();
+        let mut tables = small_set(&params);
+        let hash = [0u8; 32];
+        let progress = indicatif::ProgressBar::hidden();
+
+        tables.open_tail_cache(&path).unwrap();
+        let result = tables.search_all(&hash, &Budget::default(), &progress);
+        assert_eq!(tables.tail_cache.as_ref().unwrap().save().unwrap(), 1);
+
+        // tables of another index share the tails
+        params.table_index = 1;
+        let mut other = small_set(&params);
+        assert_eq!(other.cache_key(), tables.cache_key());
+        other.open_tail_cache(&path).unwrap();
+        let cache = other.tail_cache.as_ref().unwrap();
+        assert_eq!(cache.get(&hash).len(), 50);
+        let cached = other.search_all(&hash, &Budget::default(), &progress);
+        assert_eq!(cached.searched(), result.searched());
+        // every candidate tail is taken from cache instead of computed
+        assert_eq!(tables.tail_cache.as_ref().unwrap().hits(), 0);
+        assert_eq!(other.tail_cache.as_ref().unwrap().hits(), 50);
+        let results = other.search_batch(&other.tables[0], &[hash], &Budget::default(), &progress);
+        assert_eq!(results[0].searched(), result.searched());
+        assert_eq!(other.tail_cache.as_ref().unwrap().hits(), 100);
+
+        // but not tables of another chain length
+        params.chain_len = 40;
+        let mut other = small_set(&params);
+        assert_ne!(other.cache_key(), tables.cache_key());
+        other.open_tail_cache(&path).unwrap();
+        assert!(other.tail_cache.as_ref().unwrap().is_empty());
+
+        // a file that is not a cache is reported as such
+        std::fs::write(&path, b"not a tail cache").unwrap();
+        assert!(matches!(
+            other.open_tail_cache(&path),
+            Err(Error::InvalidCache(_, _))
+        ));
+        std::fs::write(&path, b"short").unwrap();
+        assert!(matches!(
+            other.open_tail_cache(&path),
+            Err(Error::InvalidCache(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_catalog() {
+        let dir = TempDir::new("catalog");
+        dir.write_tables("a", &small_params(), 2);
+        let mut params = TableParams::from_charset(Charset::from_chars("abc"), 1, 3).unwrap();
+        params.num_chain = 5;
+        params.chain_len = 4;
+        dir.write_tables("b", &params, 1);
+        // tables of another number of chains are searched together
+        let mut params = small_params();
+        params.num_chain = 10;
+        dir.write_tables("c", &params, 1);
+        std::fs::write(dir.join("junk.dat"), b"not a table").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+
+        // groups are ordered by plain text space size
+        let catalog = Catalog::scan(&dir.path, &[]).unwrap();
+        assert_eq!(catalog.groups.len(), 2);
+        assert_eq!(catalog.groups[0].tables.len(), 1);
+        assert_eq!(catalog.groups[0].space.size(), 3 + 9 + 27);
+        assert_eq!(catalog.groups[1].tables.len(), 3);
+        assert!(catalog.groups[1]
+            .description()
+            .starts_with("3 tables of 50 chains in total"));
+        assert_eq!(catalog.skipped.len(), 1);
+        assert!(catalog.skipped[0].0.ends_with("junk.dat"));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let metadata = Metadata {
+            generator: Some("generate_rt 0.1.0".to_owned()),
+            created: Some(1600000000),
+            chains_generated: Some(25),
+            tags: vec![("owner".to_owned(), "a=b".to_owned())],
+            unknown: vec![(100, vec![1, 2, 3])],
+            ..Default::default()
+        };
+        assert_eq!(Metadata::decode(&metadata.encode()), Ok(metadata.clone()));
+        assert_eq!(Metadata::decode(&[]), Ok(Metadata::default()));
+        let encoded = metadata.encode();
+        assert!(Metadata::decode(&encoded[..encoded.len() - 1]).is_err());
+
+        // stored after the chains, which are read as before
+        let dir = TempDir::new("metadata");
+        let path = dir.join("meta.dat");
+        let mut params = TableParams::from_charset(Charset::from_chars("abc"), 1, 3).unwrap();
+        params.num_chain = 5;
+        params.chain_len = 4;
+        let mut table = small_table(&params);
+        let table_len = table.as_bytes().len();
+        table.metadata = metadata.clone();
+        let mut file = std::fs::File::create(&path).unwrap();
+        assert_eq!(
+            table.write_to(&mut file).unwrap(),
+            table_len + encoded.len()
+        );
+        drop(file);
+        let read = RainbowTable::open(&path).unwrap();
+        assert_eq!(read.metadata, metadata);
+        assert_eq!(read.chains::<u64>(), table.chains::<u64>());
+    }
+
+    #[test]
+    fn test_brute_force() {
+        let charset = Charset::from_chars("abc");
+        let space = PlaintextSpace::from_charset(&charset, &(1..4)).unwrap();
+        let mut hashes = [[0u8; 32]; 3];
+        hashes[0].copy_from_slice(&sm3::MY_SM3(b"ab"));
+        hashes[1].copy_from_slice(&sm3::MY_SM3(b"ccc"));
+        let progress = indicatif::ProgressBar::hidden();
+
+        let results = brute_force(
+            &space,
+            0..space.size(),
+            &hashes,
+            true,
+            &Budget::default(),
+            &progress,
+        )
+        .unwrap();
+        assert_eq!(results[0].cracks[0].plain, b"ab");
+        assert_eq!(results[1].cracks[0].plain, b"ccc");
+        assert_eq!(results[2].status(), "failed");
+
+        // "ccc" is the last plain text, not in a sub-range
+        let results =
+            brute_force(&space, 0..12, &hashes, false, &Budget::default(), &progress).unwrap();
+        assert_eq!(results[0].status(), "cracked");
+        assert_eq!(results[1].status(), "failed");
+        assert!(brute_force(&space, 0..40, &hashes, false, &Budget::default(), &progress).is_err());
+
+        // lengths covered by tables are skipped
+        let covered = PlaintextSpace::from_charset(&charset, &(2..4)).unwrap();
+        let uncovered = uncovered_space(&space, &[&covered]).unwrap();
+        assert_eq!(uncovered.size(), 3);
+        assert!(uncovered_space(&covered, &[&space]).is_none());
+    }
+
+    #[test]
+    fn test_plaintext_iter() {
+        let wordlist = Arc::new(Wordlist::from_bytes(b"hello\nab\n"));
+        let spaces = vec![
+            PlaintextSpace::from_charset(&Charset::from_chars("abc"), &(1..4)).unwrap(),
+            // elements of different lengths move the positions after them
+            PlaintextSpace::from_charset(&Charset::from_tokens(&["x", "yy", "张"]), &(0..4))
+                .unwrap(),
+            PlaintextSpace::from_masks(&["?d?l".to_owned(), "a?d{2}".to_owned()], &[]).unwrap(),
+            PlaintextSpace::from_segments(vec![
+                Segment::Wordlist {
+                    wordlist,
+                    rules: parse_rules(":,cd1,t").unwrap(),
+                },
+                Segment::Mask {
+                    positions: vec![Charset::from_chars("01"); 2],
+                },
+            ])
+            .unwrap(),
+        ];
+        for space in &spaces {
+            let mut expected = vec![0u8; space.max_len()];
+            // from the start and from the middle of a segment
+            for &start in &[0, space.size() / 3] {
+                let mut iter = PlaintextIter::new(space, start..space.size());
+                let mut index = start;
+                while let Some(plaintext) = iter.next_plaintext() {
+                    let len = RainbowIndex::<u128>(index).to_plaintext(space, &mut expected);
+                    assert_eq!(plaintext, &expected[..len], "index {}", index);
+                    assert_eq!(iter.index(), index);
+                    index += 1;
+                }
+                assert_eq!(index, space.size());
+            }
+        }
+
+        let space = &spaces[0];
+        let mut iter = PlaintextIter::new(space, 5..7);
+        assert_eq!(iter.next_plaintext(), Some(&b"ca"[..]));
+        assert_eq!(iter.next_plaintext(), Some(&b"ab"[..]));
+        assert_eq!(iter.next_plaintext(), None);
+        assert_eq!(PlaintextIter::new(space, 3..3).next_plaintext(), None);
+    }
+}
@@ -0,0 +1,79 @@
+mod common;
+
+#[cfg(test)]
+mod server_tests {
+
+    use super::*;
+    use common::{head_plaintext, small_params, small_set};
+    use sm3::server::{serve, Client, JobState, ServerOptions};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    // serve a small table on a free local port
+    fn start_server(options: ServerOptions) -> (std::net::SocketAddr, Vec<u8>, String) {
+        let tables = small_set(&small_params());
+        let (plaintext, hash) = head_plaintext(&tables);
+        let hash = hex::encode(hash);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, tables, options));
+        (addr, plaintext, hash)
+    }
+
+    #[test]
+    fn test_lookup_server() {
+        let (addr, plaintext, hash) = start_server(ServerOptions::default());
+        let mut client = Client::connect(addr).unwrap();
+
+        let reports = client
+            .lookup(&[hash.clone(), "0".repeat(64)], Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].hash, hash);
+        assert_eq!(reports[0].status, "cracked");
+        assert_eq!(
+            reports[0].plaintexts,
+            vec![String::from_utf8(plaintext).unwrap()]
+        );
+        assert_eq!(reports[1].status, "failed");
+        // results are fetched only once
+        assert!(client.result(1).is_err());
+
+        let job = client.submit(&[hash]).unwrap();
+        assert_eq!(client.status(job).unwrap().total, Some(1));
+        assert!(client.submit(&["abc"]).is_err());
+        assert!(client.status(job + 100).is_err());
+        assert!(client.request("HELLO").is_err());
+    }
+
+    #[test]
+    fn test_server_limit() {
+        let options = ServerOptions {
+            max_pending: 0,
+            ..Default::default()
+        };
+        let (addr, _, hash) = start_server(options);
+        let mut client = Client::connect(addr).unwrap();
+        assert!(client.submit(&[hash]).is_err());
+    }
+
+    #[test]
+    fn test_server_eviction() {
+        let options = ServerOptions {
+            max_done: 1,
+            ..Default::default()
+        };
+        let (addr, _, hash) = start_server(options);
+        let mut client = Client::connect(addr).unwrap();
+        let first = client.submit(std::slice::from_ref(&hash)).unwrap();
+        let second = client.submit(&[hash]).unwrap();
+        while client.status(second).unwrap().state != Some(JobState::Done) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // only the latest finished job is kept
+        assert!(client.status(first).is_err());
+        assert_eq!(client.result(second).unwrap().results.unwrap().len(), 1);
+        assert!(client.status(second).is_err());
+    }
+}
@@ -50,4 +50,17 @@ mod sm3_tests {
             assert_eq!(my_result.as_ref(), openssl_result.as_ref());
         }
     }
+
+    #[test]
+    fn test_prefix_state() {
+        for _ in 0..1000 {
+            let len = rand::random::<usize>() % 80;
+            let random_bytes = (0..len).map(|_| rand::random::<u8>()).collect::<Vec<u8>>();
+            let prefix_len = rand::random::<usize>() % (len + 1);
+            let state = sm3::my_sm3_impl::PrefixState::new(&random_bytes[..prefix_len]);
+            let mut output = [0u8; 32];
+            state.hash(&random_bytes, &mut output);
+            assert_eq!(&output[..], MY_SM3(&random_bytes).as_ref());
+        }
+    }
 }
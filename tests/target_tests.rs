@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod target_tests {
+
+    use sm3::potfile::{decode_plain, encode_plain, Potfile};
+    use sm3::target::{read_targets, Target};
+
+    const HASH: &str = "66c7f0f462eeedd9d1f2d46bdc10e4e24167c4875cf2f7a2297da02b8f4ba8e0";
+
+    #[test]
+    fn test_parse_target() {
+        let t = Target::parse(HASH).unwrap();
+        assert_eq!(t.user, None);
+        assert_eq!(t.salt, None);
+        assert_eq!(t.hash_str(), HASH);
+
+        let t = Target::parse(&format!("alice:{}", HASH.to_uppercase())).unwrap();
+        assert_eq!(t.user.as_deref(), Some("alice"));
+        assert_eq!(t.hash_str(), HASH);
+        assert_eq!(t.label(), format!("alice ({})", HASH));
+
+        let t = Target::parse(&format!("{}:pepper", HASH)).unwrap();
+        assert_eq!(t.user, None);
+        assert_eq!(t.salt.as_deref(), Some("pepper"));
+
+        assert!(Target::parse("abc").is_err());
+        assert!(Target::parse(&HASH[1..]).is_err());
+        assert!(Target::parse("alice:bob").is_err());
+        assert!(Target::parse(&format!("a:{}:b", HASH)).is_err());
+
+        let input = format!("# comment\nalice:{}\r\n\nnot a hash\n{}\n", HASH, HASH);
+        let (targets, malformed) = read_targets(input.as_bytes()).unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].user.as_deref(), Some("alice"));
+        assert_eq!(malformed.len(), 1);
+        assert_eq!(malformed[0].0, 4);
+    }
+
+    #[test]
+    fn test_potfile() {
+        let path = std::env::temp_dir().join(format!("sm3_test_{}.pot", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut potfile = Potfile::load(&path).unwrap();
+        assert!(potfile.get(HASH).is_none());
+        let plains = vec![
+            b"a:b".to_vec(),
+            b"line\nbreak".to_vec(),
+            b"\xff\xfe".to_vec(),
+            "张".as_bytes().to_vec(),
+            b"$HEX[41]".to_vec(),
+        ];
+        potfile.append(HASH, &plains).unwrap();
+        potfile.append(HASH, &plains[..1]).unwrap();
+
+        let potfile = Potfile::load(&path).unwrap();
+        assert_eq!(potfile.get(HASH), Some(&plains));
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 5);
+        assert!(content.contains(":$HEX[fffe]\n"));
+        assert!(content.contains(":张\n"));
+        assert_eq!(encode_plain(b"tab\t"), "$HEX[74616209]");
+        assert_eq!(decode_plain("$HEX[fffe]"), Some(b"\xff\xfe".to_vec()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}